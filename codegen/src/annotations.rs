@@ -0,0 +1,312 @@
+use prost::Message;
+use prost_types::{DescriptorProto, EnumDescriptorProto, FileDescriptorSet};
+
+// As for validation, the descriptors are decoded a second time into these to
+// recover the extensions set on their options. Only the Envoy, udpa and xds
+// annotations about deprecation, migration and stability are kept.
+
+#[derive(Clone, PartialEq, Message)]
+struct AnnotationSet {
+    #[prost(message, repeated, tag = "1")]
+    file: Vec<AnnotatedFile>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct AnnotatedFile {
+    #[prost(message, repeated, tag = "4")]
+    message_type: Vec<AnnotatedMessage>,
+    #[prost(message, repeated, tag = "5")]
+    enum_type: Vec<AnnotatedEnum>,
+    #[prost(message, optional, tag = "8")]
+    options: Option<FileOptions>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FileOptions {
+    /// `(udpa.annotations.file_status)`
+    #[prost(message, optional, tag = "222707719")]
+    udpa_file_status: Option<StatusAnnotation>,
+    /// `(xds.annotations.v3.file_status)`
+    #[prost(message, optional, tag = "226829418")]
+    xds_file_status: Option<StatusAnnotation>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct AnnotatedMessage {
+    #[prost(message, repeated, tag = "2")]
+    field: Vec<AnnotatedField>,
+    #[prost(message, repeated, tag = "3")]
+    nested_type: Vec<AnnotatedMessage>,
+    #[prost(message, repeated, tag = "4")]
+    enum_type: Vec<AnnotatedEnum>,
+    #[prost(message, optional, tag = "7")]
+    options: Option<MessageOptions>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct MessageOptions {
+    #[prost(bool, optional, tag = "3")]
+    deprecated: Option<bool>,
+    /// `(xds.annotations.v3.message_status)`
+    #[prost(message, optional, tag = "226829418")]
+    status: Option<StatusAnnotation>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct AnnotatedField {
+    #[prost(message, optional, tag = "8")]
+    options: Option<FieldOptions>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FieldOptions {
+    #[prost(bool, optional, tag = "3")]
+    deprecated: Option<bool>,
+    /// `(envoy.annotations.disallowed_by_default)`
+    #[prost(bool, optional, tag = "189503207")]
+    disallowed_by_default: Option<bool>,
+    /// `(envoy.annotations.deprecated_at_minor_version)`
+    #[prost(string, optional, tag = "157299826")]
+    deprecated_at_minor_version: Option<String>,
+    /// `(udpa.annotations.field_migrate)`
+    #[prost(message, optional, tag = "171962766")]
+    migrate: Option<MigrateAnnotation>,
+    /// `(xds.annotations.v3.field_status)`
+    #[prost(message, optional, tag = "226829418")]
+    status: Option<StatusAnnotation>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct AnnotatedEnum {
+    #[prost(message, repeated, tag = "2")]
+    value: Vec<AnnotatedEnumValue>,
+    #[prost(message, optional, tag = "3")]
+    options: Option<EnumOptions>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct EnumOptions {
+    #[prost(bool, optional, tag = "3")]
+    deprecated: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct AnnotatedEnumValue {
+    #[prost(message, optional, tag = "3")]
+    options: Option<EnumValueOptions>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct EnumValueOptions {
+    #[prost(bool, optional, tag = "1")]
+    deprecated: Option<bool>,
+    /// `(envoy.annotations.disallowed_by_default_enum)`
+    #[prost(bool, optional, tag = "70100853")]
+    disallowed_by_default: Option<bool>,
+    /// `(envoy.annotations.deprecated_at_minor_version_enum)`
+    #[prost(string, optional, tag = "181198657")]
+    deprecated_at_minor_version: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct StatusAnnotation {
+    #[prost(bool, optional, tag = "1")]
+    work_in_progress: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct MigrateAnnotation {
+    #[prost(string, optional, tag = "1")]
+    rename: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    oneof_promotion: Option<String>,
+}
+
+const WORK_IN_PROGRESS: &str = "#[doc = \"\"]\n#[doc = \" **Work in progress:** this API is not covered by Envoy's \
+    stability guarantees and may change in breaking ways.\"]";
+
+fn get_doc_note(text: &str) -> String {
+    format!("#[doc = \"\"]\n#[doc = \" {}\"]", text.escape_default())
+}
+
+fn get_deprecated(since: Option<&str>, disallowed: bool) -> String {
+    let mut note = match since {
+        Some(version) => format!("deprecated since Envoy API v{version}"),
+        None => "deprecated in the Envoy API".to_string(),
+    };
+
+    if disallowed {
+        note.push_str(", and rejected by Envoy unless allowed by a runtime override");
+    }
+
+    format!("#[deprecated(note = {note:?})]")
+}
+
+#[derive(Clone, Copy, Default)]
+struct Markers {
+    deprecated: bool,
+    work_in_progress: bool,
+}
+
+/// Attributes to add to the generated code, keyed by the fully qualified
+/// proto path they apply to.
+#[derive(Default)]
+struct Attributes {
+    /// Whether deprecations are noted for the file at hand. Outside the Envoy
+    /// API, prost-build's bare `#[deprecated]` is left as is.
+    envoy_api: bool,
+    messages: Vec<(String, String)>,
+    enums: Vec<(String, String)>,
+    fields: Vec<(String, String)>,
+}
+
+impl Attributes {
+    fn add_enum(&mut self, full_name: &str, item: &EnumDescriptorProto, annotated: &AnnotatedEnum, work_in_progress: bool) {
+        if self.envoy_api && annotated.options.as_ref().is_some_and(|x| x.deprecated()) {
+            self.enums.push((full_name.to_string(), get_deprecated(None, false)));
+        }
+
+        if work_in_progress {
+            self.enums.push((full_name.to_string(), WORK_IN_PROGRESS.to_string()));
+        }
+
+        for (value, annotated) in item.value.iter().zip(&annotated.value) {
+            let options = annotated.options.clone().unwrap_or_default();
+
+            if self.envoy_api && (options.deprecated() || options.deprecated_at_minor_version.is_some()) {
+                self.fields.push((
+                    format!("{full_name}.{}", value.name()),
+                    get_deprecated(options.deprecated_at_minor_version.as_deref(), options.disallowed_by_default()),
+                ));
+            }
+        }
+    }
+
+    /// Collects the attributes of a message and its nested types. Message
+    /// attributes apply to nested messages too, so `inherited` keeps them
+    /// from being repeated there.
+    fn add_message(
+        &mut self,
+        full_name: &str,
+        message: &mut DescriptorProto,
+        annotated: &AnnotatedMessage,
+        inherited: Markers,
+    ) {
+        let options = annotated.options.clone().unwrap_or_default();
+        let markers = Markers {
+            deprecated: options.deprecated(),
+            work_in_progress: options.status.as_ref().is_some_and(|x| x.work_in_progress()),
+        };
+
+        if self.envoy_api && markers.deprecated && !inherited.deprecated {
+            self.messages.push((full_name.to_string(), get_deprecated(None, false)));
+        }
+
+        if markers.work_in_progress && !inherited.work_in_progress {
+            self.messages.push((full_name.to_string(), WORK_IN_PROGRESS.to_string()));
+        }
+
+        let inherited = Markers {
+            deprecated: inherited.deprecated || markers.deprecated,
+            work_in_progress: inherited.work_in_progress || markers.work_in_progress,
+        };
+
+        for (field, annotated) in message.field.iter_mut().zip(&annotated.field) {
+            let options = annotated.options.clone().unwrap_or_default();
+            let path = match field.oneof_index {
+                Some(index) if !field.proto3_optional() => {
+                    format!("{full_name}.{}.{}", message.oneof_decl[index as usize].name(), field.name())
+                },
+                _ => format!("{full_name}.{}", field.name()),
+            };
+
+            if self.envoy_api && (options.deprecated() || options.deprecated_at_minor_version.is_some()) {
+                // prost-build emits a bare `#[deprecated]` for these, which
+                // would clash with the one carrying a note.
+                if let Some(options) = field.options.as_mut() {
+                    options.deprecated = None;
+                }
+
+                self.fields.push((
+                    path.clone(),
+                    get_deprecated(options.deprecated_at_minor_version.as_deref(), options.disallowed_by_default()),
+                ));
+            }
+
+            if options.status.is_some_and(|x| x.work_in_progress()) {
+                self.fields.push((path.clone(), WORK_IN_PROGRESS.to_string()));
+            }
+
+            if let Some(migrate) = &options.migrate {
+                if let Some(name) = &migrate.rename {
+                    self.fields.push((path.clone(), get_doc_note(&format!("Renamed to `{name}` in the next major version of the API."))));
+                }
+
+                if let Some(name) = &migrate.oneof_promotion {
+                    self.fields.push((path.clone(), get_doc_note(&format!("Moves into the `{name}` oneof in the next major version of the API."))));
+                }
+            }
+        }
+
+        for (nested, annotated) in message.nested_type.iter_mut().zip(&annotated.nested_type) {
+            let nested_name = format!("{full_name}.{}", nested.name());
+            self.add_message(&nested_name, nested, annotated, inherited);
+        }
+
+        for (item, annotated) in message.enum_type.iter().zip(&annotated.enum_type) {
+            let enum_name = format!("{full_name}.{}", item.name());
+            self.add_enum(&enum_name, item, annotated, false);
+        }
+    }
+}
+
+/// Marks deprecated messages, fields and enum values of the Envoy API with
+/// `#[deprecated]`, noting the API version they were deprecated in, and
+/// documents work-in-progress APIs and fields renamed in the next major
+/// version.
+///
+/// The fields are updated to drop the plain `deprecated` option, which
+/// prost-build would otherwise turn into a bare `#[deprecated]`.
+pub fn add_annotations(config: &mut prost_build::Config, descriptors: &mut FileDescriptorSet, descriptor_bytes: &[u8]) {
+    let annotated = AnnotationSet::decode(descriptor_bytes).unwrap();
+    let mut attributes = Attributes::default();
+
+    for (file, annotated) in descriptors.file.iter_mut().zip(&annotated.file) {
+        let work_in_progress = annotated.options.as_ref().is_some_and(|x| {
+            x.udpa_file_status.as_ref().is_some_and(|x| x.work_in_progress())
+                || x.xds_file_status.as_ref().is_some_and(|x| x.work_in_progress())
+        });
+
+        if work_in_progress {
+            for message in &file.message_type {
+                attributes.messages.push((format!(".{}.{}", file.package(), message.name()), WORK_IN_PROGRESS.to_string()));
+            }
+        }
+
+        let package = file.package().to_string();
+        attributes.envoy_api = package.starts_with("envoy.");
+
+        for (message, annotated) in file.message_type.iter_mut().zip(&annotated.message_type) {
+            let full_name = format!(".{package}.{}", message.name());
+            let inherited = Markers { work_in_progress, ..Default::default() };
+            attributes.add_message(&full_name, message, annotated, inherited);
+        }
+
+        for (item, annotated) in file.enum_type.iter().zip(&annotated.enum_type) {
+            let full_name = format!(".{package}.{}", item.name());
+            attributes.add_enum(&full_name, item, annotated, work_in_progress);
+        }
+    }
+
+    for (path, attribute) in attributes.messages {
+        config.message_attribute(path, attribute);
+    }
+
+    for (path, attribute) in attributes.enums {
+        config.enum_attribute(path, attribute);
+    }
+
+    for (path, attribute) in attributes.fields {
+        config.field_attribute(path, attribute);
+    }
+}
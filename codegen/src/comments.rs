@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use prost_types::source_code_info::Location;
+use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+use regex::{Captures, Regex};
+
+const ENVOY_DOCS_URL: &str = "https://www.envoyproxy.io/docs/envoy";
+const ENVOY_REPO_URL: &str = "https://github.com/envoyproxy/envoy/blob";
+
+// Field numbers of `FileDescriptorProto.message_type` and
+// `DescriptorProto.nested_type`, used in source code info paths.
+const FILE_MESSAGE_TYPE: i32 = 4;
+const MESSAGE_NESTED_TYPE: i32 = 3;
+
+static ANNOTATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[#[^\]]*\]").unwrap());
+static EXTERNAL_LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"`([^`<]+?)\s*<(https?://[^>]+)>`__?").unwrap());
+static LITERAL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"``([^`]+)``").unwrap());
+static ROLE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r":([\w-]+):`([^`]+)`").unwrap());
+static ROLE_TARGET: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(.*?)\s*<([^>]+)>$").unwrap());
+static API_LABEL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^envoy_v3_api_(?:msg|field|enum|enum_value)_(.+)$").unwrap());
+static CODE_BLOCK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\.\. (?:code-block|code|sourcecode|validated-code-block)::\s*([\w+-]*)").unwrap()
+});
+static ADMONITION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\.\. (attention|caution|danger|hint|important|note|tip|warning|seealso)::\s*(.*)$").unwrap()
+});
+static DIRECTIVE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\.\. [\w-]+::").unwrap());
+static DIRECTIVE_OPTION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^:[\w-]+:").unwrap());
+
+/// Resolves Envoy API references to their page in the upstream docs.
+struct Links {
+    version: String,
+    files: HashMap<String, String>,
+}
+
+impl Links {
+    fn new(descriptors: &FileDescriptorSet, api_version: &str) -> Links {
+        let mut files = HashMap::new();
+
+        for file in &descriptors.file {
+            let mut stack: Vec<(String, &DescriptorProto)> = file.message_type.iter()
+                .map(|x| (file.package().to_string(), x))
+                .collect();
+
+            for item in &file.enum_type {
+                files.insert(format!("{}.{}", file.package(), item.name()), file.name().to_string());
+            }
+
+            while let Some((prefix, message)) = stack.pop() {
+                let full_name = format!("{prefix}.{}", message.name());
+
+                for item in &message.enum_type {
+                    files.insert(format!("{full_name}.{}", item.name()), file.name().to_string());
+                }
+
+                stack.extend(message.nested_type.iter().map(|x| (full_name.clone(), x)));
+                files.insert(full_name, file.name().to_string());
+            }
+        }
+
+        Links {
+            version: format!("v{api_version}.0"),
+            files,
+        }
+    }
+
+    fn get_page_url(&self, file_name: &str) -> Option<String> {
+        file_name.strip_prefix("envoy/")
+            .map(|x| format!("{ENVOY_DOCS_URL}/{}/api-v3/{x}", self.version))
+    }
+
+    /// Returns the docs URL of an API label such as
+    /// `envoy_v3_api_field_config.cluster.v3.Cluster.name`.
+    fn get_label_url(&self, label: &str) -> Option<String> {
+        let name = format!("envoy.{}", API_LABEL.captures(label)?.get(1)?.as_str());
+        let mut prefix = name.as_str();
+
+        let file_name = loop {
+            if let Some(file_name) = self.files.get(prefix) {
+                break file_name;
+            }
+            prefix = &prefix[..prefix.rfind('.')?];
+        };
+
+        Some(format!("{}#{}", self.get_page_url(file_name)?, get_anchor(label)))
+    }
+
+    fn get_repo_url(&self, path: &str) -> String {
+        format!("{ENVOY_REPO_URL}/{}/{}", self.version, path.trim_start_matches('/'))
+    }
+}
+
+/// Converts a Sphinx label to the HTML id it is rendered with.
+fn get_anchor(label: &str) -> String {
+    label.chars()
+        .map(|x| match x.is_ascii_alphanumeric() {
+            true => x.to_ascii_lowercase(),
+            false => '-',
+        })
+        .collect()
+}
+
+fn split_role_target(content: &str) -> (Option<&str>, &str) {
+    match ROLE_TARGET.captures(content) {
+        Some(captures) => (Some(captures.get(1).unwrap().as_str()), captures.get(2).unwrap().as_str()),
+        None => (None, content),
+    }
+}
+
+fn replace_role(captures: &Captures, links: &Links) -> String {
+    let (text, target) = split_role_target(&captures[2]);
+
+    match &captures[1] {
+        "ref" => {
+            let text = match text {
+                Some(text) => text.to_string(),
+                None => match API_LABEL.captures(target) {
+                    Some(name) => format!("`{}`", &name[1]),
+                    None => format!("`{target}`"),
+                },
+            };
+
+            match links.get_label_url(target) {
+                Some(url) => format!("[{text}]({url})"),
+                None => text,
+            }
+        },
+        "repo" => format!("[`{}`]({})", text.unwrap_or(target), links.get_repo_url(target)),
+        _ => format!("`{}`", text.unwrap_or(target)),
+    }
+}
+
+/// Escapes `<` and `>` outside inline code, so rustdoc does not take them
+/// for HTML tags.
+fn escape_html(line: &str) -> String {
+    line.split('`')
+        .enumerate()
+        .map(|(index, part)| match index % 2 {
+            0 => part.replace('<', "&lt;").replace('>', "&gt;"),
+            _ => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("`")
+}
+
+fn sanitize_inline(line: &str, links: &Links) -> String {
+    let line = ANNOTATION.replace_all(line, "");
+    let line = EXTERNAL_LINK.replace_all(&line, "[$1]($2)");
+    let line = LITERAL.replace_all(&line, "`$1`");
+    let line = escape_html(&line);
+    ROLE.replace_all(&line, |x: &Captures| replace_role(x, links)).into_owned()
+}
+
+fn get_indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Consumes the block indented deeper than `indent` starting at `start`,
+/// returning its lines dedented and the index following it.
+fn take_block<'a>(lines: &[&'a str], start: usize, indent: usize) -> (Vec<&'a str>, usize) {
+    let mut end = start;
+
+    while end < lines.len() && (lines[end].trim().is_empty() || get_indent(lines[end]) > indent) {
+        end += 1;
+    }
+
+    let mut block: Vec<&str> = lines[start..end].to_vec();
+
+    while block.last().is_some_and(|x| x.trim().is_empty()) {
+        block.pop();
+    }
+
+    while block.first().is_some_and(|x| x.trim().is_empty()) {
+        block.remove(0);
+    }
+
+    let dedent = block.iter()
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| get_indent(x))
+        .min()
+        .unwrap_or(0);
+
+    let block = block.into_iter()
+        .map(|x| x.get(dedent..).unwrap_or(""))
+        .collect();
+
+    (block, end)
+}
+
+fn write_fence(output: &mut Vec<String>, block: Vec<&str>, language: &str) {
+    if block.is_empty() {
+        return;
+    }
+
+    // Anything but Rust keeps rustdoc from running the block as a doctest.
+    let language = match language {
+        "" | "rust" => "text",
+        language => language,
+    };
+
+    output.push(format!("```{language}"));
+    output.extend(block.into_iter().map(|x| x.replace("```", "'''")));
+    output.push("```".to_string());
+    output.push(String::new());
+}
+
+/// Rewrites the reStructuredText constructs used in the Envoy protos into
+/// Markdown rustdoc can render. Code and literal blocks become fenced blocks
+/// that are not doctested, and no indented code blocks are left behind.
+fn sanitize_text(text: &str, links: &Links) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut output: Vec<String> = vec![];
+    let mut literal_next = false;
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = lines[index];
+        let trimmed = line.trim();
+        let indent = get_indent(line);
+
+        if trimmed.is_empty() {
+            output.push(String::new());
+            index += 1;
+            continue;
+        }
+
+        let previous_blank = output.last().is_none_or(|x| x.is_empty());
+
+        if previous_blank && (literal_next || indent >= 4) {
+            let base = match literal_next {
+                true => indent.saturating_sub(1),
+                false => 0,
+            };
+            let (block, end) = take_block(&lines, index, base);
+            write_fence(&mut output, block, "text");
+            literal_next = false;
+            index = end;
+            continue;
+        }
+
+        literal_next = false;
+
+        if let Some(captures) = CODE_BLOCK.captures(trimmed) {
+            let mut start = index + 1;
+
+            while start < lines.len() && DIRECTIVE_OPTION.is_match(lines[start].trim()) {
+                start += 1;
+            }
+
+            let (block, end) = take_block(&lines, start, indent);
+            write_fence(&mut output, block, &captures[1]);
+            index = end;
+            continue;
+        }
+
+        if let Some(captures) = ADMONITION.captures(trimmed) {
+            let title = format!("{}{}", captures[1][..1].to_uppercase(), &captures[1][1..]);
+            output.push(format!("**{title}:** {}", sanitize_inline(&captures[2], links)).trim_end().to_string());
+
+            let (block, end) = take_block(&lines, index + 1, indent);
+            output.extend(sanitize_text(&block.join("\n"), links).lines().map(String::from));
+            output.push(String::new());
+            index = end;
+            continue;
+        }
+
+        if DIRECTIVE.is_match(trimmed) {
+            let (block, end) = take_block(&lines, index + 1, indent);
+            let block = block.into_iter().filter(|x| !DIRECTIVE_OPTION.is_match(x)).collect();
+            write_fence(&mut output, block, "text");
+            index = end;
+            continue;
+        }
+
+        if trimmed.starts_with("..") {
+            // Labels (`.. _name:`) and comments.
+            let (_, end) = take_block(&lines, index + 1, indent);
+            index = end;
+            continue;
+        }
+
+        let mut sanitized = sanitize_inline(trimmed, links);
+
+        if let Some(paragraph) = sanitized.strip_suffix("::") {
+            literal_next = true;
+            sanitized = match paragraph.trim_end() {
+                "" => String::new(),
+                paragraph => format!("{paragraph}:"),
+            };
+        }
+
+        // Keep list continuations, but never enough indentation to start
+        // an indented code block.
+        output.push(format!("{}{sanitized}", " ".repeat(indent.min(3))).trim_end().to_string());
+        index += 1;
+    }
+
+    while output.last().is_some_and(|x| x.is_empty()) {
+        output.pop();
+    }
+
+    output.join("\n")
+}
+
+fn sanitize_location(location: &mut Location, links: &Links) {
+    for comment in location.leading_comments.iter_mut().chain(location.trailing_comments.iter_mut()) {
+        *comment = sanitize_text(comment, links);
+    }
+
+    for comment in &mut location.leading_detached_comments {
+        *comment = sanitize_text(comment, links);
+    }
+}
+
+fn collect_message_paths(paths: &mut Vec<(Vec<i32>, String)>, path: Vec<i32>, full_name: String, message: &DescriptorProto) {
+    for (index, nested) in message.nested_type.iter().enumerate() {
+        let mut nested_path = path.clone();
+        nested_path.extend([MESSAGE_NESTED_TYPE, index as i32]);
+        collect_message_paths(paths, nested_path, format!("{full_name}.{}", nested.name()), nested);
+    }
+
+    paths.push((path, full_name));
+}
+
+/// Appends a link to the upstream docs to the comments of every message of
+/// an Envoy API file.
+fn add_doc_links(file: &mut FileDescriptorProto, links: &Links) {
+    let page_url = match links.get_page_url(file.name()) {
+        Some(url) => url,
+        None => return,
+    };
+
+    let mut paths = vec![];
+
+    for (index, message) in file.message_type.iter().enumerate() {
+        let full_name = format!("{}.{}", file.package(), message.name());
+        collect_message_paths(&mut paths, vec![FILE_MESSAGE_TYPE, index as i32], full_name, message);
+    }
+
+    let source_code_info = file.source_code_info.get_or_insert_with(Default::default);
+
+    for (path, full_name) in paths {
+        let label = format!("envoy_v3_api_msg_{}", full_name.trim_start_matches("envoy."));
+        let link = format!("See the [upstream documentation]({page_url}#{}).", get_anchor(&label));
+
+        let location = match source_code_info.location.iter_mut().position(|x| x.path == path) {
+            Some(position) => &mut source_code_info.location[position],
+            None => {
+                source_code_info.location.push(Location { path, ..Default::default() });
+                source_code_info.location.last_mut().unwrap()
+            },
+        };
+
+        location.leading_comments = match location.leading_comments.take() {
+            Some(comments) if !comments.trim().is_empty() => Some(format!("{}\n\n{link}", comments.trim_end())),
+            _ => Some(link),
+        };
+    }
+}
+
+/// Rewrites the comments of `descriptors` into valid rustdoc and links each
+/// Envoy message to its upstream docs.
+pub fn sanitize_comments(descriptors: &mut FileDescriptorSet, api_version: &str) {
+    let links = Links::new(descriptors, api_version);
+
+    for file in &mut descriptors.file {
+        if let Some(source_code_info) = &mut file.source_code_info {
+            for location in &mut source_code_info.location {
+                sanitize_location(location, &links);
+            }
+        }
+
+        add_doc_links(file, &links);
+    }
+}
@@ -0,0 +1,18 @@
+//! The code generator behind envoypb's `regenerate` feature.
+//!
+//! It is kept apart from envoypb so that none of its dependencies are ever
+//! enabled for envoypb itself. It is meant to be called from envoypb's build
+//! script, and reads envoypb's features from the `CARGO_FEATURE_*` variables
+//! cargo sets there. envoypb's `xtask` also calls it without any feature to
+//! write the pre-generated sources under `generated/`.
+
+mod annotations;
+mod comments;
+mod metadata;
+mod packages;
+mod regenerate;
+mod registry;
+mod types;
+mod validate;
+
+pub use regenerate::{generate, get_api_versions, get_github_digests, GitHubDigest};
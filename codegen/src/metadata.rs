@@ -0,0 +1,82 @@
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+/// Where to get a dependency from instead of its upstream GitHub repository,
+/// as configured through the environment or the package metadata.
+#[derive(Default)]
+pub struct SourceConfig {
+    /// An extracted source tree or a `.tar.gz` on disk.
+    pub path: Option<PathBuf>,
+    /// A `.tar.gz` to download.
+    pub url: Option<String>,
+    /// The SHA-256 digest of the tarball at `url`, or of the upstream tarball
+    /// at `git_ref`.
+    pub sha256: Option<String>,
+    /// A git repository to fetch.
+    pub git: Option<String>,
+    /// The ref to check out from `git`, defaulting to the pinned upstream ref,
+    /// or without `git` the ref of the upstream repository to download.
+    pub git_ref: Option<String>,
+}
+
+/// The `[package.metadata.envoypb]` table, or `[workspace.metadata.envoypb]`,
+/// of the manifest named by `ENVOYPB_CONFIG`, defaulting to envoypb's own.
+/// Relative paths in it are resolved against the manifest's directory.
+pub struct Metadata {
+    dir: PathBuf,
+    table: toml::Table,
+}
+
+impl Metadata {
+    pub fn load() -> Option<Metadata> {
+        let path = match env::var_os("ENVOYPB_CONFIG") {
+            Some(path) => PathBuf::from(path),
+            None => Path::new(&env::var_os("CARGO_MANIFEST_DIR")?).join("Cargo.toml"),
+        };
+
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let text = fs::read_to_string(&path)
+            .unwrap_or_else(|error| panic!("failed to read {}: {error}", path.display()));
+        let manifest: toml::Table = text.parse()
+            .unwrap_or_else(|error| panic!("failed to parse {}: {error}", path.display()));
+
+        let table = ["package", "workspace"].into_iter().find_map(|section| {
+            manifest.get(section)?.get("metadata")?.get("envoypb")?.as_table().cloned()
+        })?;
+
+        Some(Metadata {
+            dir: path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+            table,
+        })
+    }
+
+    fn get_path(&self, value: &toml::Value) -> PathBuf {
+        match value.as_str() {
+            Some(path) => self.dir.join(path),
+            None => panic!("envoypb metadata paths must be strings, found {value}"),
+        }
+    }
+
+    /// Returns the source configured in `[...envoypb.dependencies.<key>]`.
+    pub fn get_source(&self, key: &str) -> Option<SourceConfig> {
+        let table = self.table.get("dependencies")?.get(key)?;
+        let get = |name: &str| table.get(name).and_then(|x| x.as_str()).map(String::from);
+
+        Some(SourceConfig {
+            path: table.get("path").map(|x| self.get_path(x)),
+            url: get("url"),
+            sha256: get("sha256"),
+            git: get("git"),
+            git_ref: get("ref"),
+        })
+    }
+
+    /// Returns the extra proto roots listed in `proto-roots`.
+    pub fn get_proto_roots(&self) -> Vec<PathBuf> {
+        match self.table.get("proto-roots").and_then(|x| x.as_array()) {
+            Some(roots) => roots.iter().map(|x| self.get_path(x)).collect(),
+            None => vec![],
+        }
+    }
+}
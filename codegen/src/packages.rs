@@ -0,0 +1,223 @@
+use std::collections::{BTreeSet, HashMap};
+use std::{env, fs};
+use std::io::Write;
+use std::path::Path;
+use phf::phf_ordered_map;
+use prost_build::Module;
+use prost_types::FileDescriptorSet;
+
+/// Maps proto path prefixes to the cargo feature that enables them, most
+/// specific first. `None` marks the core packages, which are always compiled.
+const PACKAGE_GROUPS: phf::OrderedMap<&str, Option<&str>> = phf_ordered_map!{
+    "envoy/config/bootstrap/"              => Some("config-bootstrap"),
+    "envoy/config/cluster/"                => None,
+    "envoy/config/core/"                   => None,
+    "envoy/config/endpoint/"               => None,
+    "envoy/config/listener/"               => None,
+    "envoy/config/route/"                  => None,
+    "envoy/config/"                        => Some("config"),
+    "envoy/extensions/access_loggers/"     => Some("extensions-access-loggers"),
+    "envoy/extensions/clusters/"           => Some("extensions-clusters"),
+    "envoy/extensions/filters/http/"       => Some("extensions-filters-http"),
+    "envoy/extensions/filters/listener/"   => Some("extensions-filters-listener"),
+    "envoy/extensions/filters/network/"    => Some("extensions-filters-network"),
+    "envoy/extensions/filters/udp/"        => Some("extensions-filters-udp"),
+    "envoy/extensions/transport_sockets/"  => Some("extensions-transport-sockets"),
+    "envoy/extensions/"                    => Some("extensions"),
+    "envoy/service/accesslog/"             => Some("service-accesslog"),
+    "envoy/service/auth/"                  => Some("service-auth"),
+    "envoy/service/cluster/"               => None,
+    "envoy/service/discovery/"             => None,
+    "envoy/service/endpoint/"              => None,
+    "envoy/service/ext_proc/"              => Some("service-ext-proc"),
+    "envoy/service/extension/"             => None,
+    "envoy/service/health/"                => Some("service-health"),
+    "envoy/service/listener/"              => None,
+    "envoy/service/load_stats/"            => Some("service-load-stats"),
+    "envoy/service/metrics/"               => Some("service-metrics"),
+    "envoy/service/rate_limit_quota/"      => Some("service-rate-limit-quota"),
+    "envoy/service/ratelimit/"             => Some("service-ratelimit"),
+    "envoy/service/route/"                 => None,
+    "envoy/service/runtime/"               => None,
+    "envoy/service/secret/"                => None,
+    "envoy/service/status/"                => Some("service-status"),
+    "envoy/service/tap/"                   => Some("service-tap"),
+    "envoy/service/trace/"                 => Some("service-trace"),
+    "envoy/service/"                       => Some("service"),
+    "envoy/admin/"                         => Some("admin"),
+    "envoy/data/"                          => Some("data"),
+    "envoy/type/"                          => None,
+    "envoy/watchdog/"                      => Some("watchdog"),
+    "contrib/"                             => Some("contrib"),
+    "envoy/"                               => Some("full"),
+};
+
+/// The package of the well-known types, shared between API versions.
+const SHARED_PACKAGE: [&str; 2] = ["google", "protobuf"];
+
+/// Returns the crate the well-known types are taken from with `extern-wkt`
+/// instead of being compiled: pbjson-types when `serde` needs their JSON
+/// mapping, prost-types otherwise.
+pub fn get_wkt_crate() -> Option<&'static str> {
+    env::var_os("CARGO_FEATURE_EXTERN_WKT")?;
+
+    match env::var_os("CARGO_FEATURE_SERDE") {
+        Some(_) => Some("::pbjson_types"),
+        None => Some("::prost_types"),
+    }
+}
+
+fn get_package_group(file_name: &str) -> Option<Option<&'static str>> {
+    PACKAGE_GROUPS.entries()
+        .find(|(prefix, _)| file_name.starts_with(*prefix))
+        .map(|(_, group)| *group)
+}
+
+/// Returns the features that enable each package, computed as the import
+/// closure of every file in each group. An empty set means the package is
+/// needed by the core packages and is never gated.
+pub fn get_package_features(descriptors: &FileDescriptorSet) -> HashMap<String, BTreeSet<&'static str>> {
+    let files: HashMap<&str, _> = descriptors.file.iter()
+        .map(|x| (x.name(), x))
+        .collect();
+
+    let mut file_groups: HashMap<&str, BTreeSet<Option<&str>>> = HashMap::new();
+
+    for file in &descriptors.file {
+        let group = match get_package_group(file.name()) {
+            Some(group) => group,
+            None => continue,
+        };
+
+        let mut stack = vec![file.name()];
+
+        while let Some(name) = stack.pop() {
+            if file_groups.entry(name).or_default().insert(group) {
+                stack.extend(files[name].dependency.iter().map(|x| x.as_str()));
+            }
+        }
+    }
+
+    let mut packages: HashMap<String, BTreeSet<Option<&str>>> = HashMap::new();
+
+    for file in &descriptors.file {
+        let groups = file_groups.get(file.name()).cloned().unwrap_or_default();
+        packages.entry(file.package().to_string()).or_default().extend(groups);
+    }
+
+    packages.into_iter()
+        .map(|(package, groups)| {
+            let features = match groups.contains(&None) {
+                true => BTreeSet::new(),
+                false => groups.into_iter().flatten().collect(),
+            };
+            (package, features)
+        })
+        .collect()
+}
+
+/// Returns the `cfg` predicate enabling a package, or `None` if it is always
+/// compiled.
+pub fn get_cfg_predicate(features: &BTreeSet<&str>) -> Option<String> {
+    if features.is_empty() {
+        return None;
+    }
+
+    let predicates: Vec<String> = features.iter()
+        .map(|x| format!("feature = \"{x}\""))
+        .collect();
+
+    Some(format!("any({})", predicates.join(", ")))
+}
+
+pub fn write_line(buffer: &mut Vec<u8>, depth: usize, line: &str) {
+    writeln!(buffer, "{}{line}", "    ".repeat(depth)).unwrap();
+}
+
+/// Writes `mod.rs` in the same layout as prost-build's include file, gating
+/// each package on the features whose import closure contains it. Serde
+/// implementations generated by pbjson and the generated `Validate`
+/// implementations are included alongside each package.
+///
+/// With `multi-version`, every API version shares the well-known types
+/// compiled at the crate root, so their package re-exports those instead.
+/// With `extern-wkt`, it re-exports the crate they are taken from, but for
+/// envoypb's own `Any`.
+pub fn write_include_file(out_dir: &Path, descriptors: &FileDescriptorSet) {
+    let mut modules: Vec<(Module, BTreeSet<&str>)> = get_package_features(descriptors)
+        .into_iter()
+        .map(|(package, features)| (Module::from_protobuf_package_name(&package), features))
+        .collect();
+    modules.sort();
+
+    let mut buffer = vec![];
+    let mut stack: Vec<&str> = vec![];
+
+    write_line(&mut buffer, 0, "// This file is @generated by the envoypb build script.");
+
+    for (module, features) in &modules {
+        let parts: Vec<&str> = module.parts().collect();
+
+        while !parts.starts_with(&stack) {
+            stack.pop();
+            write_line(&mut buffer, stack.len(), "}");
+        }
+
+        while stack.len() < parts.len() {
+            // Deprecated fields and enum values are still referenced by the
+            // generated trait implementations. Downstream uses are warned
+            // about as usual.
+            if stack.is_empty() {
+                write_line(&mut buffer, 0, "#[allow(clippy::all, deprecated)]");
+            }
+
+            write_line(&mut buffer, stack.len(), &format!("pub mod {} {{", parts[stack.len()]));
+            stack.push(parts[stack.len()]);
+        }
+
+        let mut predicate = get_cfg_predicate(features);
+        let mut is_compiled = true;
+
+        if module.parts().eq(SHARED_PACKAGE) {
+            match get_wkt_crate() {
+                Some(path) => {
+                    write_line(&mut buffer, stack.len(), &format!("pub use {path}::*;"));
+                    write_line(&mut buffer, stack.len(), "pub use crate::any::Any;");
+                    is_compiled = false;
+                },
+                None => {
+                    write_line(&mut buffer, stack.len(), "#[cfg(feature = \"multi-version\")]");
+                    write_line(&mut buffer, stack.len(), "pub use crate::google::protobuf::*;");
+                },
+            }
+
+            predicate = Some("not(feature = \"multi-version\")".to_string());
+        }
+
+        if is_compiled {
+            if let Some(predicate) = &predicate {
+                write_line(&mut buffer, stack.len(), &format!("#[cfg({predicate})]"));
+            }
+
+            write_line(&mut buffer, stack.len(), &format!("include!(\"{}\");", module.to_file_name_or("_")));
+        }
+
+        for feature in ["serde", "validate"] {
+            let file = format!("{}.{feature}.rs", parts.join("."));
+
+            if out_dir.join(&file).is_file() {
+                match &predicate {
+                    Some(predicate) => write_line(&mut buffer, stack.len(), &format!("#[cfg(all(feature = \"{feature}\", {predicate}))]")),
+                    None => write_line(&mut buffer, stack.len(), &format!("#[cfg(feature = \"{feature}\")]")),
+                }
+                write_line(&mut buffer, stack.len(), &format!("include!(\"{file}\");"));
+            }
+        }
+    }
+
+    for depth in (0..stack.len()).rev() {
+        write_line(&mut buffer, depth, "}");
+    }
+
+    fs::write(out_dir.join("mod.rs"), buffer).unwrap();
+}
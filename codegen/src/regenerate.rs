@@ -0,0 +1,824 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::{env, error, fs, io, thread};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use crate::{annotations, comments, packages, registry, validate};
+use crate::metadata::{Metadata, SourceConfig};
+use glob::glob;
+use prost::Message;
+use prost_types::FileDescriptorSet;
+use phf::{phf_map, phf_ordered_map};
+use sha2::{Digest, Sha256};
+use tar::Archive;
+use flate2::read::GzDecoder;
+
+type StringResult = Result<String, Box<dyn error::Error>>;
+
+const GITHUB_API: &str = "https://api.github.com";
+const GITHUB_HOSTS: [&str; 3] = ["api.github.com", "codeload.github.com", "github.com"];
+const DOWNLOAD_ATTEMPTS: u32 = 4;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Returns the base URLs of the GitHub API to download from, tried in order.
+/// GitHub Enterprise and Artifactory remotes serve the same API.
+fn get_github_mirrors() -> Vec<String> {
+    match env::var("ENVOYPB_GITHUB_MIRRORS") {
+        Ok(mirrors) => mirrors.split([',', ' ', '\n'])
+            .filter(|x| !x.is_empty())
+            .map(|x| x.trim_end_matches('/').to_string())
+            .collect(),
+        Err(_) => vec![GITHUB_API.to_string()],
+    }
+}
+
+fn get_github_tarball_uris(org: &str, repo: &str, ref_: &str) -> Vec<String> {
+    get_github_mirrors()
+        .into_iter()
+        .map(|base| format!("{base}/repos/{org}/{repo}/tarball/{ref_}"))
+        .collect()
+}
+
+fn get_host(uri: &str) -> &str {
+    let rest = uri.split_once("://").map_or(uri, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+
+    host.split(':').next().unwrap_or(host)
+}
+
+fn get_env(vars: &[&str]) -> Option<String> {
+    vars.iter().find_map(|x| env::var(x).ok().filter(|x| !x.is_empty()))
+}
+
+fn is_proxy_bypassed(host: &str) -> bool {
+    get_env(&["NO_PROXY", "no_proxy"]).unwrap_or_default()
+        .split(',')
+        .map(|x| x.trim().trim_start_matches("*.").trim_start_matches('.'))
+        .filter(|x| !x.is_empty())
+        .any(|x| x == "*" || host == x || host.ends_with(&format!(".{x}")))
+}
+
+fn get_proxy(uri: &str) -> Result<Option<ureq::Proxy>, String> {
+    if is_proxy_bypassed(get_host(uri)) {
+        return Ok(None);
+    }
+
+    let proxy = match uri.starts_with("https:") {
+        true => get_env(&["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]),
+        false => get_env(&["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]),
+    };
+
+    match proxy {
+        Some(proxy) => ureq::Proxy::new(&proxy)
+            .map(Some)
+            .map_err(|error| format!("invalid proxy {proxy}: {error}")),
+        None => Ok(None),
+    }
+}
+
+/// Returns the token to authenticate with, keeping `GITHUB_TOKEN` for GitHub
+/// itself so it is never sent to a mirror.
+fn get_token(uri: &str) -> Option<String> {
+    match GITHUB_HOSTS.contains(&get_host(uri)) {
+        true => get_env(&["GITHUB_TOKEN"]),
+        false => get_env(&["ENVOYPB_MIRROR_TOKEN"]),
+    }
+}
+
+/// A failed download, and whether trying again may succeed.
+struct FetchError {
+    message: String,
+    retryable: bool,
+}
+
+fn fetch(uri: &str, path: &Path) -> Result<(), FetchError> {
+    let fatal = |message: String| FetchError { message, retryable: false };
+    let transient = |message: String| FetchError { message, retryable: true };
+
+    let mut builder = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .user_agent(concat!("envoypb/", env!("CARGO_PKG_VERSION")));
+
+    if let Some(proxy) = get_proxy(uri).map_err(fatal)? {
+        builder = builder.proxy(proxy);
+    }
+
+    let mut request = builder.build().get(uri);
+
+    if let Some(token) = get_token(uri) {
+        request = request.set("Authorization", &format!("Bearer {token}"));
+    }
+
+    let resp = match request.call() {
+        Ok(resp) => resp,
+        Err(ureq::Error::Status(code, resp)) => {
+            let message = format!("HTTP {code} {}", resp.status_text());
+            return Err(FetchError { message, retryable: code == 429 || code >= 500 });
+        },
+        Err(error) => return Err(transient(error.to_string())),
+    };
+
+    let mut file = File::create(path).map_err(|error| fatal(error.to_string()))?;
+    io::copy(&mut resp.into_reader(), &mut file).map_err(|error| transient(error.to_string()))?;
+
+    Ok(())
+}
+
+/// Downloads the first of `uris` that succeeds to `path`, retrying transient
+/// failures with exponential backoff, and returns the URL it came from.
+fn download<'a>(path: &Path, key: &str, ref_: &str, uris: &'a [String]) -> Result<&'a str, Box<dyn error::Error>> {
+    let mut failures = vec![];
+
+    for uri in uris {
+        for attempt in 1..=DOWNLOAD_ATTEMPTS {
+            let error = match fetch(uri, path) {
+                Ok(()) => return Ok(uri),
+                Err(error) => error,
+            };
+
+            log(&format!("downloading {key} at {ref_} from {uri} failed (attempt {attempt}): {}", error.message));
+
+            if !error.retryable || attempt == DOWNLOAD_ATTEMPTS {
+                failures.push(format!("{uri}: {}", error.message));
+                break;
+            }
+
+            thread::sleep(Duration::from_secs(1 << (attempt - 1)));
+        }
+    }
+
+    Err(format!("failed to download {key} at {ref_} ({})", failures.join("; ")).into())
+}
+
+fn get_cache_dir() -> Option<PathBuf> {
+    match env::var_os("ENVOYPB_CACHE_DIR") {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => match env::var_os("XDG_CACHE_HOME") {
+            Some(dir) => Some(Path::new(&dir).join("envoypb")),
+            None => env::var_os("HOME").map(|dir| Path::new(&dir).join(".cache").join("envoypb")),
+        },
+    }
+}
+
+fn get_file_digest(path: &Path) -> StringResult {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(hasher.finalize().iter().map(|x| format!("{x:02x}")).collect())
+}
+
+fn store_in_cache(cache_path: &Path, path: &Path) -> Result<(), Box<dyn error::Error>> {
+    fs::create_dir_all(cache_path.parent().unwrap())?;
+    let partial_path = cache_path.with_extension("partial");
+    fs::copy(path, &partial_path)?;
+    fs::rename(&partial_path, cache_path)?;
+
+    Ok(())
+}
+
+fn download_tarball(target: &Path, key: &str, ref_: &str, expected: Option<&str>, uris: &[String]) -> StringResult {
+    let cache_path = match (expected, get_cache_dir()) {
+        (Some(digest), Some(dir)) => Some(dir.join("sha256").join(format!("{digest}.tar.gz"))),
+        _ => None,
+    };
+
+    if let Some(path) = &cache_path {
+        if path.is_file() && Some(get_file_digest(path)?.as_str()) == expected {
+            return unpack_tarball(target, path);
+        }
+    }
+
+    // Downloaded next to the directory it is unpacked into, under OUT_DIR.
+    let path = target.with_extension("tar.gz");
+    let uri = download(&path, key, ref_, uris)?;
+    let digest = get_file_digest(&path)?;
+
+    match expected {
+        Some(expected) if expected != digest => {
+            return Err(format!("sha256 mismatch for {key} at {ref_} from {uri}: expected {expected}, got {digest}").into());
+        },
+        Some(_) => {
+            if let Some(cache_path) = &cache_path {
+                store_in_cache(cache_path, &path)?;
+            }
+        },
+        None if env::var_os("ENVOYPB_REQUIRE_PINNED").is_some() => {
+            return Err(format!("{key} at {ref_} is not pinned to a digest, downloaded sha256 is {digest}").into());
+        },
+        None => println!("cargo:warning={key} at {ref_} is not pinned to a digest, downloaded sha256 is {digest}"),
+    }
+
+    unpack_tarball(target, &path)
+}
+
+fn unpack_tarball(target: &Path, path: &Path) -> StringResult {
+    let mut archive = Archive::new(GzDecoder::new(File::open(path)?));
+    archive.unpack(target).map_err(|error| format!("failed to unpack {}: {error}", path.display()))?;
+
+    // GitHub tarballs hold a single directory named after the repository and
+    // commit, and `target` is emptied before unpacking.
+    let mut dirs: Vec<PathBuf> = fs::read_dir(target)?
+        .filter_map(Result::ok)
+        .map(|x| x.path())
+        .filter(|x| x.is_dir())
+        .collect();
+
+    match dirs.len() {
+        1 => Ok(dirs.pop().unwrap().to_str().unwrap().to_string()),
+        count => Err(format!("expected {} to hold a single top-level directory, found {count}", path.display()).into()),
+    }
+}
+
+/// Checks out `ref_` of the repository at `url` into `target`. Only that
+/// commit is fetched, so `ref_` may be a branch, tag or full commit hash.
+fn checkout(target: &Path, key: &str, url: &str, ref_: &str) -> StringResult {
+    let steps: [&[&str]; 3] = [
+        &["init", "--quiet"],
+        &["fetch", "--quiet", "--depth", "1", url, ref_],
+        &["checkout", "--quiet", "FETCH_HEAD"],
+    ];
+
+    for args in steps {
+        // Build script output is read by cargo, so git must not write to it.
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(target)
+            .args(args)
+            .stdout(Stdio::null())
+            .status()
+            .map_err(|error| format!("failed to run git for {key}: {error}"))?;
+
+        if !status.success() {
+            return Err(format!("git {} failed for {key} at {ref_} from {url}: {status}", args[0]).into());
+        }
+    }
+
+    Ok(target.to_str().unwrap().to_string())
+}
+
+/// A git ref together with the SHA-256 digest of its GitHub tarball, if known.
+#[derive(Clone, Copy)]
+struct Pin(&'static str, Option<&'static str>);
+
+/// The digest of the GitHub tarball of a dependency at its pinned ref.
+pub struct GitHubDigest {
+    pub key: &'static str,
+    pub ref_: &'static str,
+    /// The digest currently pinned for the ref, if any.
+    pub pinned: Option<&'static str>,
+    pub digest: String,
+}
+
+/// Downloads the GitHub tarball of every dependency at the ref pinned for
+/// `version` to `dir`, to pin the digests of new refs.
+pub fn get_github_digests(version: &str, dir: &Path) -> Result<Vec<GitHubDigest>, Box<dyn error::Error>> {
+    fs::create_dir_all(dir)?;
+
+    BUILD_DEPS.into_iter()
+        .filter_map(|(key, dep)| match dep {
+            Dependency::GitHub(org, repo) => Some((*key, *org, *repo)),
+            _ => None,
+        })
+        .map(|(key, org, repo)| {
+            let Pin(ref_, pinned) = get_github_ref(key, version);
+            let path = dir.join(format!("{key}.tar.gz"));
+            download(&path, key, ref_, &get_github_tarball_uris(org, repo, ref_))?;
+
+            Ok(GitHubDigest { key, ref_, pinned, digest: get_file_digest(&path)? })
+        })
+        .collect()
+}
+
+fn get_github_ref(key: &str, version: &str) -> Pin {
+    match GITHUB_BUILD_DEP_REFS.get(version) {
+        Some(refs) => {
+            match refs.get(key) {
+                Some(x) => *x,
+                None => *GITHUB_DEFAULT_BUILD_DEP_REFS.get(key).unwrap(),
+            }
+        },
+        None => panic!("unsupported version: {version}"),
+    }
+}
+
+#[derive(Clone)]
+enum Dependency {
+    /// A GitHub repository, at the ref pinned for the API version.
+    GitHub(&'static str, &'static str),
+    /// A GitHub repository at another ref, with the SHA-256 digest of its
+    /// tarball if known.
+    GitHubAt(&'static str, &'static str, String, Option<String>),
+    /// A source tree or `.tar.gz` on disk.
+    Local(PathBuf),
+    /// A `.tar.gz` to download, with its SHA-256 digest if known.
+    Tarball(String, Option<String>),
+    /// A git repository and the ref to check out.
+    Git(String, String),
+}
+
+impl Dependency {
+    fn is_local(&self) -> bool {
+        matches!(self, Dependency::Local(_))
+    }
+
+    fn get_contents(self, target: &Path, key: &str, version: &str) -> StringResult {
+        match self {
+            Dependency::GitHub(org, repo) => {
+                let Pin(ref_, expected) = get_github_ref(key, version);
+                let uris = get_github_tarball_uris(org, repo, ref_);
+                download_tarball(target, key, ref_, expected, &uris)
+            },
+            Dependency::GitHubAt(org, repo, ref_, expected) => {
+                let uris = get_github_tarball_uris(org, repo, &ref_);
+                download_tarball(target, key, &ref_, expected.as_deref(), &uris)
+            },
+            Dependency::Local(path) => match LocalSource::from_path(path.clone()) {
+                Some(source) => source.get_contents(target),
+                None => Err(format!("the source of {key} is not a directory or tarball: {}", path.display()).into()),
+            },
+            Dependency::Tarball(uri, expected) => {
+                download_tarball(target, key, &uri, expected.as_deref(), std::slice::from_ref(&uri))
+            },
+            Dependency::Git(url, ref_) => checkout(target, key, &url, &ref_),
+        }
+    }
+}
+
+enum LocalSource {
+    Directory(PathBuf),
+    Tarball(PathBuf),
+}
+
+impl LocalSource {
+    fn from_path(path: PathBuf) -> Option<LocalSource> {
+        if path.is_dir() {
+            Some(LocalSource::Directory(path))
+        } else if path.is_file() {
+            Some(LocalSource::Tarball(path))
+        } else {
+            None
+        }
+    }
+
+    fn get_contents(self, target: &Path) -> StringResult {
+        match self {
+            LocalSource::Directory(path) => Ok(path.to_str().unwrap().to_string()),
+            LocalSource::Tarball(path) => unpack_tarball(target, &path),
+        }
+    }
+}
+
+/// Suffixes of the per-dependency `ENVOYPB_<KEY>_<SUFFIX>` overrides.
+const SOURCE_VARS: [&str; 5] = ["PATH", "URL", "SHA256", "GIT", "REF"];
+
+fn get_source_var(key: &str, suffix: &str) -> String {
+    format!("ENVOYPB_{}_{suffix}", key.to_uppercase())
+}
+
+fn get_env_source(key: &str) -> SourceConfig {
+    let get = |suffix| env::var(get_source_var(key, suffix)).ok().filter(|x| !x.is_empty());
+
+    SourceConfig {
+        path: get("PATH").map(PathBuf::from),
+        url: get("URL"),
+        sha256: get("SHA256"),
+        git: get("GIT"),
+        git_ref: get("REF"),
+    }
+}
+
+fn get_configured_source(config: SourceConfig, key: &str, dep: &Dependency, version: &str) -> Option<Dependency> {
+    if let Some(path) = config.path {
+        println!("cargo:rerun-if-changed={}", path.display());
+        return Some(Dependency::Local(path));
+    }
+
+    if let Some(url) = config.url {
+        return Some(Dependency::Tarball(url, config.sha256));
+    }
+
+    let Some(git) = config.git else {
+        // A ref alone selects another ref of the upstream repository.
+        return match (config.git_ref, dep) {
+            (Some(ref_), Dependency::GitHub(org, repo)) => Some(Dependency::GitHubAt(org, repo, ref_, config.sha256)),
+            (Some(_), _) => panic!("no repository to fetch the ref of {key} from, set {}", get_source_var(key, "GIT")),
+            (None, _) => None,
+        };
+    };
+
+    let ref_ = config.git_ref.unwrap_or_else(|| get_github_ref(key, version).0.to_string());
+
+    Some(Dependency::Git(git, ref_))
+}
+
+/// Picks the source of a dependency: the environment overrides come first,
+/// then the package metadata, then `ENVOYPB_LOCAL_DEPS` and finally upstream.
+fn get_source(key: &str, dep: &Dependency, version: &str, metadata: Option<&Metadata>) -> Dependency {
+    if let Some(source) = get_configured_source(get_env_source(key), key, dep, version) {
+        return source;
+    }
+
+    if let Some(config) = metadata.and_then(|x| x.get_source(key)) {
+        if let Some(source) = get_configured_source(config, key, dep, version) {
+            return source;
+        }
+    }
+
+    if let Some(root) = env::var_os("ENVOYPB_LOCAL_DEPS").map(PathBuf::from) {
+        println!("cargo:rerun-if-changed={}", root.display());
+
+        for path in [root.join(key), root.join(format!("{key}.tar.gz"))] {
+            if path.exists() {
+                return Dependency::Local(path);
+            }
+        }
+    }
+
+    dep.clone()
+}
+
+/// Returns the directories of extra protos to compile along with Envoy's,
+/// from `ENVOYPB_PROTO_ROOTS` and the package metadata.
+fn get_proto_roots(metadata: Option<&Metadata>) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = match env::var_os("ENVOYPB_PROTO_ROOTS") {
+        Some(paths) => env::split_paths(&paths).filter(|x| !x.as_os_str().is_empty()).collect(),
+        None => vec![],
+    };
+
+    if let Some(metadata) = metadata {
+        roots.append(&mut metadata.get_proto_roots());
+    }
+
+    roots
+}
+
+fn is_offline() -> bool {
+    env::var_os("ENVOYPB_OFFLINE").is_some()
+}
+
+const BUILD_DEPS: phf::OrderedMap<&str, Dependency> = phf_ordered_map!{
+    "envoy"         => Dependency::GitHub("envoyproxy", "envoy"),
+    "xds"           => Dependency::GitHub("cncf", "xds"),
+    "validate"      => Dependency::GitHub("bufbuild", "protoc-gen-validate"),
+    "googleapis"    => Dependency::GitHub("googleapis", "googleapis"),
+    "opencensus"    => Dependency::GitHub("census-instrumentation", "opencensus-proto"),
+    "opentelemetry" => Dependency::GitHub("open-telemetry", "opentelemetry-proto"),
+    "prometheus"    => Dependency::GitHub("prometheus", "client_model"),
+    // `xds/type/v3/cel.proto` imports `cel/expr/*.proto` in newer releases.
+    "cel"           => Dependency::GitHub("google", "cel-spec"),
+};
+
+const GITHUB_BUILD_DEP_REFS: phf::Map<&str, phf::Map<&str, Pin>> = phf_map!(
+    "1.35" => phf_map!(
+        "envoy"         => Pin("v1.35.0", None),
+        "xds"           => Pin("b4127c9b8d78b77423fd25169f05b7476b6ea932", None),
+        "validate"      => Pin("v1.2.1", None),
+        "googleapis"    => Pin("114a745b2841a044e98cdbb19358ed29fcf4a5f1", None),
+    ),
+    "1.34" => phf_map!(
+        "envoy"         => Pin("v1.34.0", None),
+        "xds"           => Pin("b4127c9b8d78b77423fd25169f05b7476b6ea932", None),
+        "validate"      => Pin("v1.2.1", None),
+        "googleapis"    => Pin("114a745b2841a044e98cdbb19358ed29fcf4a5f1", None),
+    ),
+    "1.33" => phf_map!(
+        "envoy"         => Pin("v1.33.0", None),
+        "xds"           => Pin("b4127c9b8d78b77423fd25169f05b7476b6ea932", None),
+        "validate"      => Pin("v1.0.4", None),
+        "googleapis"    => Pin("114a745b2841a044e98cdbb19358ed29fcf4a5f1", None),
+    ),
+    "1.32" => phf_map!(
+        "envoy" => Pin("v1.32.0", None),
+    ),
+    "1.31" => phf_map!(
+        "envoy" => Pin("v1.31.0", None),
+    ),
+    "1.30" => phf_map!(),
+);
+
+const GITHUB_DEFAULT_BUILD_DEP_REFS: phf::Map<&str, Pin> = phf_map!(
+    "envoy"         => Pin("v1.30.0", None),
+    "xds"           => Pin("cff3c89139a3e6a0d4fbddfd158ad895e9b30840", None),
+    "validate"      => Pin("v1.1.1-SNAPSHOT.22", None),
+    "googleapis"    => Pin("b819b9552ddb98c5d2f68719c34b729cfa370fcc", None),
+    "opencensus"    => Pin("v0.4.1", None),
+    "opentelemetry" => Pin("v1.5.0", None),
+    "prometheus"    => Pin("v0.6.1", None),
+    "cel"           => Pin("v0.15.0", None),
+);
+
+/// Returns the Envoy API versions refs are pinned for, oldest first.
+pub fn get_api_versions() -> Vec<&'static str> {
+    let mut versions: Vec<&str> = GITHUB_BUILD_DEP_REFS.keys().copied().collect();
+    versions.sort_by_key(|x| x.split('.').map(|x| x.parse::<u32>().unwrap()).collect::<Vec<_>>());
+    versions
+}
+
+const BUILD_DEP_DIRS: phf::Map<&str, &str> = phf_map!{
+    "opencensus" => "src",
+    "cel"        => "proto",
+};
+
+/// Versions of the Envoy API packages to compile, along with the features
+/// that enable them. Only the stable v3 packages are compiled by default.
+const API_PACKAGE_VERSIONS: [(&str, Option<&str>); 4] = [
+    ("v3", None),
+    ("v3alpha", Some("API_V3ALPHA")),
+    ("v4alpha", Some("API_V4ALPHA")),
+    ("v2", Some("API_V2")),
+];
+
+/// Directories of the cncf/xds repository holding its protos, compiled in
+/// full with the `cncf-xds` feature instead of only what Envoy imports.
+const XDS_PROTO_DIRS: [&str; 2] = ["udpa", "xds"];
+
+fn get_imports(path: &Path) -> Vec<String> {
+    fs::read_to_string(path).unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let import = line.trim().strip_prefix("import ")?;
+            let import = import.trim_start_matches("public ").trim_start_matches("weak ");
+            Some(import.trim().strip_prefix('"')?.split('"').next()?.to_string())
+        })
+        .collect()
+}
+
+/// Whether the proto named `name` and everything it imports can be found in
+/// `includes`. protoc ships the well-known types itself.
+fn is_resolvable(name: &str, includes: &[String], resolved: &mut HashMap<String, bool>) -> bool {
+    if name.starts_with("google/protobuf/") {
+        return true;
+    }
+
+    if let Some(result) = resolved.get(name) {
+        return *result;
+    }
+
+    // Imports cannot be cyclic, but this keeps a malformed tree from
+    // recursing forever.
+    resolved.insert(name.to_string(), true);
+
+    let result = match includes.iter().map(|x| Path::new(x).join(name)).find(|x| x.is_file()) {
+        Some(path) => get_imports(&path).iter().all(|x| is_resolvable(x, includes, resolved)),
+        None => false,
+    };
+
+    resolved.insert(name.to_string(), result);
+    result
+}
+
+/// Returns every cncf/xds proto under `contents_path` whose imports can be
+/// resolved. Some import protos from repositories Envoy itself does not
+/// depend on, and are skipped.
+fn get_xds_protos(contents_path: &Path, includes: &[String]) -> Vec<String> {
+    let mut resolved = HashMap::new();
+    let mut protos = vec![];
+
+    for dir in XDS_PROTO_DIRS {
+        let pattern = format!("{}/**/*.proto", contents_path.join(dir).to_str().unwrap());
+
+        for path in glob(&pattern).unwrap().filter_map(Result::ok) {
+            let imports = get_imports(&path);
+
+            if imports.iter().all(|x| is_resolvable(x, includes, &mut resolved)) {
+                protos.push(path.to_str().unwrap().to_string());
+            } else {
+                log(&format!("skipping {}, some of its imports are missing", path.display()));
+            }
+        }
+    }
+
+    protos
+}
+
+fn get_api_package_versions() -> Vec<&'static str> {
+    API_PACKAGE_VERSIONS.iter()
+        .filter(|(_, feature)| match feature {
+            Some(feature) => env::var_os(format!("CARGO_FEATURE_{feature}")).is_some(),
+            None => true,
+        })
+        .map(|(version, _)| *version)
+        .collect()
+}
+
+/// `Any` is envoypb's own with `extern-wkt`, to keep its JSON mapping.
+const ANY_PATH: &str = "crate::google::protobuf::Any";
+
+/// Environment variables consulted while generating, besides the
+/// per-dependency `ENVOYPB_<KEY>_<SUFFIX>` overrides.
+const ENV_VARS: [&str; 20] = [
+    "ALL_PROXY",
+    "ENVOYPB_CACHE_DIR",
+    "ENVOYPB_CONFIG",
+    "ENVOYPB_GITHUB_MIRRORS",
+    "ENVOYPB_LOCAL_DEPS",
+    "ENVOYPB_MIRROR_TOKEN",
+    "ENVOYPB_OFFLINE",
+    "ENVOYPB_PROTO_ROOTS",
+    "ENVOYPB_REQUIRE_PINNED",
+    "ENVOYPB_VERBOSE",
+    "GITHUB_TOKEN",
+    "HOME",
+    "HTTPS_PROXY",
+    "HTTP_PROXY",
+    "NO_PROXY",
+    "XDG_CACHE_HOME",
+    "all_proxy",
+    "http_proxy",
+    "https_proxy",
+    "no_proxy",
+];
+
+/// Logs build details as cargo warnings, only when `ENVOYPB_VERBOSE` is set.
+pub fn log(message: &str) {
+    if env::var_os("ENVOYPB_VERBOSE").is_some() {
+        for line in message.lines() {
+            println!("cargo:warning={line}");
+        }
+    }
+}
+
+pub fn generate(api_version: &str, out_dir: &Path) {
+    for var in ENV_VARS {
+        println!("cargo:rerun-if-env-changed={var}");
+    }
+
+    for key in BUILD_DEPS.keys() {
+        for suffix in SOURCE_VARS {
+            println!("cargo:rerun-if-env-changed={}", get_source_var(key, suffix));
+        }
+    }
+
+    let metadata = Metadata::load();
+
+    // Each version pins its own refs, so their sources are kept apart.
+    let deps_path = Path::new(&env::var("OUT_DIR").unwrap()).join("deps").join(api_version);
+
+    fs::create_dir_all(out_dir).unwrap();
+
+    let mut protos: Vec<String> = vec![];
+    let mut includes: Vec<String> = vec![];
+    let mut xds_path = None;
+
+    for (key, dep) in BUILD_DEPS.into_iter() {
+        let dep_path = deps_path.join(key);
+
+        // Sources unpacked by a previous run may be for another ref.
+        if dep_path.exists() {
+            fs::remove_dir_all(&dep_path).unwrap();
+        }
+
+        fs::create_dir_all(&dep_path).unwrap();
+        let source = get_source(key, dep, api_version, metadata.as_ref());
+
+        if is_offline() && !source.is_local() {
+            panic!(
+                "no local source for {key} in offline mode, set {} or ENVOYPB_LOCAL_DEPS",
+                get_source_var(key, "PATH"),
+            );
+        }
+
+        let contents_dir = source.get_contents(&dep_path, key, api_version).unwrap_or_else(|error| panic!("{error}"));
+        let contents_path = Path::new(&contents_dir);
+
+        if *key == "envoy" {
+            let api_path = contents_path.join("api");
+            let api_dir = api_path.to_str().unwrap().to_string();
+
+            for package_version in get_api_package_versions() {
+                let mut xds_protos: Vec<String> = glob(&format!("{api_dir}/**/{package_version}/*.proto"))
+                    .unwrap()
+                    .filter_map(Result::ok)
+                    .map(|x| x.to_str().unwrap().to_string())
+                    .collect();
+
+                // Newer releases have dropped the v2 and v4alpha packages.
+                if xds_protos.is_empty() {
+                    log(&format!("no {package_version} packages in the Envoy API for {api_version}"));
+                }
+
+                protos.append(&mut xds_protos);
+            }
+
+            includes.push(api_dir);
+        } else {
+            if *key == "xds" && env::var_os("CARGO_FEATURE_CNCF_XDS").is_some() {
+                xds_path = Some(contents_path.to_path_buf());
+            }
+
+            match BUILD_DEP_DIRS.get(key) {
+                Some(subdir) => {
+                    let sub_path = contents_path.join(subdir);
+                    includes.push(sub_path.to_str().unwrap().to_string());
+                },
+                None => includes.push(contents_path.to_str().unwrap().to_string()),
+            }
+        }
+    }
+
+    // Resolved once every include path is known. Envoy's own imports are
+    // compiled already, so this only adds the rest.
+    if let Some(path) = xds_path {
+        let mut xds_protos = get_xds_protos(&path, &includes);
+        protos.append(&mut xds_protos);
+    }
+
+    for root in get_proto_roots(metadata.as_ref()) {
+        println!("cargo:rerun-if-changed={}", root.display());
+
+        let root_dir = root.to_str().unwrap().to_string();
+        let mut root_protos: Vec<String> = glob(&format!("{root_dir}/**/*.proto"))
+            .unwrap()
+            .filter_map(Result::ok)
+            .map(|x| x.to_str().unwrap().to_string())
+            .collect();
+
+        if root_protos.is_empty() {
+            panic!("no protos found in extra proto root {root_dir}");
+        }
+
+        protos.append(&mut root_protos);
+        includes.push(root_dir);
+    }
+
+    // Lets the build scripts of dependents compile protos importing these,
+    // through `DEP_ENVOYPB_INCLUDE`. With several API versions, the newest
+    // one is generated last and wins.
+    println!("cargo:include={}", env::join_paths(&includes).unwrap().to_str().unwrap());
+
+    env::set_var("PROTOC", protobuf_src::protoc());
+
+    let keep_comments = env::var_os("CARGO_FEATURE_COMMENTS").is_some();
+    let descriptor_path = out_dir.join("file_descriptor_set.bin");
+
+    let mut config = prost_build::Config::new();
+    config.enable_type_names();
+    // `Name::type_url` is what `Any` and the xDS type URLs are made of.
+    config.type_name_domain(["."], "type.googleapis.com");
+    config.file_descriptor_set_path(&descriptor_path);
+
+    if !keep_comments {
+        config.disable_comments(["."]);
+    }
+
+    let wkt_crate = packages::get_wkt_crate();
+
+    // prost maps the well-known types to prost-types by itself, wrappers to
+    // primitives included. Any other crate replaces that mapping.
+    if let Some(path) = wkt_crate.filter(|x| *x != "::prost_types") {
+        config.compile_well_known_types();
+        config.extern_path(".google.protobuf", path);
+    }
+
+    if wkt_crate.is_some() {
+        config.extern_path(".google.protobuf.Any", ANY_PATH);
+    }
+
+    log(&format!(
+        "compiling {} protos for Envoy API {api_version}\ninclude paths: {}",
+        protos.len(),
+        includes.join(", "),
+    ));
+
+    let mut compiled = config.load_fds(&protos, &includes).unwrap();
+    let descriptor_bytes = fs::read(&descriptor_path).unwrap();
+
+    annotations::add_annotations(&mut config, &mut compiled, &descriptor_bytes);
+
+    if keep_comments {
+        comments::sanitize_comments(&mut compiled, api_version);
+    }
+
+    tonic_build::configure()
+        .build_server(true)
+        .build_client(true)
+        .server_mod_attribute(".", "#[cfg(feature = \"server\")]")
+        .client_mod_attribute(".", "#[cfg(feature = \"client\")]")
+        .compile_well_known_types(wkt_crate.is_none())
+        .out_dir(out_dir)
+        .compile_fds_with_config(config, compiled)
+        .unwrap();
+
+    let descriptors = FileDescriptorSet::decode(descriptor_bytes.as_slice()).unwrap();
+
+    let mut json_builder = pbjson_build::Builder::new();
+    json_builder.register_descriptors(&descriptor_bytes).unwrap();
+
+    if let Some(path) = wkt_crate {
+        json_builder.extern_path(".google.protobuf", path);
+        json_builder.extern_path(".google.protobuf.Any", ANY_PATH);
+    }
+
+    json_builder
+        .exclude([".google.protobuf"])
+        .out_dir(out_dir)
+        .build(&["."]).unwrap();
+
+    validate::write_validate_files(out_dir, &descriptor_bytes, &descriptors);
+    packages::write_include_file(out_dir, &descriptors);
+    registry::write_registry_file(out_dir, &descriptors);
+}
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::Path;
+use prost_types::FileDescriptorSet;
+use crate::packages::{get_cfg_predicate, get_package_features, get_wkt_crate, write_line};
+use crate::types::get_message_types;
+
+/// Well-known types with hand-written JSON implementations in `src/json.rs`.
+/// The rest of `google.protobuf` (descriptors and the like) is not registered.
+const WELL_KNOWN_TYPES: [&str; 16] = [
+    "google.protobuf.Any",
+    "google.protobuf.BoolValue",
+    "google.protobuf.BytesValue",
+    "google.protobuf.DoubleValue",
+    "google.protobuf.Duration",
+    "google.protobuf.Empty",
+    "google.protobuf.FloatValue",
+    "google.protobuf.Int32Value",
+    "google.protobuf.Int64Value",
+    "google.protobuf.ListValue",
+    "google.protobuf.StringValue",
+    "google.protobuf.Struct",
+    "google.protobuf.Timestamp",
+    "google.protobuf.UInt32Value",
+    "google.protobuf.UInt64Value",
+    "google.protobuf.Value",
+];
+
+/// Most of the prost-types well-known types do not implement `Name`, so
+/// only envoypb's own `Any` is registered with `extern-wkt`.
+fn is_registered(package: &str, full_name: &str) -> bool {
+    match package {
+        "google.protobuf" if full_name == "google.protobuf.Any" => true,
+        "google.protobuf" => get_wkt_crate().is_none() && WELL_KNOWN_TYPES.contains(&full_name),
+        _ => true,
+    }
+}
+
+/// Writes `registry.rs`, which registers every compiled message with the
+/// runtime type registry under the same feature gates as its package. It is
+/// included next to `mod.rs`, so the paths are relative to that module.
+pub fn write_registry_file(out_dir: &Path, descriptors: &FileDescriptorSet) {
+    let features = get_package_features(descriptors);
+    let mut types = get_message_types(descriptors);
+    types.sort_by(|a, b| a.full_name.cmp(&b.full_name));
+
+    let mut buffer = vec![];
+
+    write_line(&mut buffer, 0, "// This file is @generated by the envoypb build script.");
+    write_line(&mut buffer, 0, "pub(crate) fn register_types(registry: &mut crate::TypeRegistry) {");
+
+    for message in types.iter().filter(|x| is_registered(&x.package, &x.full_name)) {
+        if let Some(predicate) = get_cfg_predicate(&features[&message.package]) {
+            write_line(&mut buffer, 1, &format!("#[cfg({predicate})]"));
+        }
+
+        write_line(&mut buffer, 1, &format!(
+            "registry.register::<self::{}>(\"{}\");",
+            message.rust_path,
+            message.full_name,
+        ));
+    }
+
+    write_line(&mut buffer, 0, "}");
+
+    fs::write(out_dir.join("registry.rs"), buffer).unwrap();
+}
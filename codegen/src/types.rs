@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use heck::{ToSnakeCase, ToUpperCamelCase};
+use prost_build::Module;
+use prost_types::{DescriptorProto, FileDescriptorSet};
+
+/// A message compiled into the crate, along with the path of its generated
+/// Rust type relative to the root of the generated modules.
+pub struct MessageType {
+    pub package: String,
+    pub full_name: String,
+    pub rust_path: String,
+}
+
+// The following mirror prost-build's private identifier rules, so the paths
+// computed here match the generated code.
+
+fn sanitize_identifier(ident: String) -> String {
+    match ident.as_str() {
+        "as" | "break" | "const" | "continue" | "else" | "enum" | "false" | "fn" | "for" | "if"
+        | "impl" | "in" | "let" | "loop" | "match" | "mod" | "move" | "mut" | "pub" | "ref"
+        | "return" | "static" | "struct" | "trait" | "true" | "type" | "unsafe" | "use"
+        | "where" | "while" | "dyn" | "abstract" | "become" | "box" | "do" | "final" | "macro"
+        | "override" | "priv" | "typeof" | "unsized" | "virtual" | "yield" | "async" | "await"
+        | "try" => format!("r#{ident}"),
+        "_" | "super" | "self" | "Self" | "extern" | "crate" => format!("{ident}_"),
+        s if s.starts_with(|c: char| c.is_numeric()) => format!("_{ident}"),
+        _ => ident,
+    }
+}
+
+pub fn to_snake(name: &str) -> String {
+    sanitize_identifier(name.to_snake_case())
+}
+
+pub fn to_upper_camel(name: &str) -> String {
+    sanitize_identifier(name.to_upper_camel_case())
+}
+
+fn collect_messages(
+    types: &mut Vec<MessageType>,
+    package: &str,
+    proto_prefix: &str,
+    rust_prefix: &str,
+    messages: &[DescriptorProto],
+) {
+    for message in messages {
+        if message.options.as_ref().is_some_and(|x| x.map_entry()) {
+            continue;
+        }
+
+        let full_name = format!("{proto_prefix}{}", message.name());
+
+        types.push(MessageType {
+            package: package.to_string(),
+            full_name: full_name.clone(),
+            rust_path: format!("{rust_prefix}{}", to_upper_camel(message.name())),
+        });
+
+        collect_messages(
+            types,
+            package,
+            &format!("{full_name}."),
+            &format!("{rust_prefix}{}::", to_snake(message.name())),
+            &message.nested_type,
+        );
+    }
+}
+
+/// Lists every message type in `descriptors`, skipping synthetic map entries.
+pub fn get_message_types(descriptors: &FileDescriptorSet) -> Vec<MessageType> {
+    let mut types = vec![];
+
+    for file in &descriptors.file {
+        let module = Module::from_protobuf_package_name(file.package());
+        let rust_prefix: String = module.parts().map(|x| format!("{x}::")).collect();
+
+        collect_messages(
+            &mut types,
+            file.package(),
+            &format!("{}.", file.package()),
+            &rust_prefix,
+            &file.message_type,
+        );
+    }
+
+    types
+}
+
+fn collect_enums(
+    paths: &mut HashMap<String, String>,
+    proto_prefix: &str,
+    rust_prefix: &str,
+    messages: &[DescriptorProto],
+) {
+    for message in messages {
+        let full_name = format!("{proto_prefix}{}", message.name());
+        let nested_prefix = format!("{rust_prefix}{}::", to_snake(message.name()));
+
+        for item in &message.enum_type {
+            paths.insert(
+                format!("{full_name}.{}", item.name()),
+                format!("{nested_prefix}{}", to_upper_camel(item.name())),
+            );
+        }
+
+        collect_enums(paths, &format!("{full_name}."), &nested_prefix, &message.nested_type);
+    }
+}
+
+/// Maps the fully qualified name of every enum in `descriptors` to the path
+/// of its generated Rust type, relative to the root of the generated modules.
+pub fn get_enum_paths(descriptors: &FileDescriptorSet) -> HashMap<String, String> {
+    let mut paths = HashMap::new();
+
+    for file in &descriptors.file {
+        let module = Module::from_protobuf_package_name(file.package());
+        let rust_prefix: String = module.parts().map(|x| format!("{x}::")).collect();
+
+        for item in &file.enum_type {
+            paths.insert(
+                format!("{}.{}", file.package(), item.name()),
+                format!("{rust_prefix}{}", to_upper_camel(item.name())),
+            );
+        }
+
+        collect_enums(&mut paths, &format!("{}.", file.package()), &rust_prefix, &file.message_type);
+    }
+
+    paths
+}
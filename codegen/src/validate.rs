@@ -0,0 +1,1301 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use prost::{Message, Oneof};
+use prost_build::Module;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+use crate::packages::{get_wkt_crate, write_line};
+use crate::regenerate::log;
+use crate::types::{get_enum_paths, to_snake, to_upper_camel};
+
+// prost_types drops the extensions set on descriptor options, so the
+// descriptors are decoded a second time into these, which only keep the
+// messages, fields and oneofs along with their protoc-gen-validate options.
+
+#[derive(Clone, PartialEq, Message)]
+struct RulesSet {
+    #[prost(message, repeated, tag = "1")]
+    file: Vec<RulesFile>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RulesFile {
+    #[prost(message, repeated, tag = "4")]
+    message_type: Vec<RulesMessage>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RulesMessage {
+    #[prost(message, repeated, tag = "2")]
+    field: Vec<RulesField>,
+    #[prost(message, repeated, tag = "3")]
+    nested_type: Vec<RulesMessage>,
+    #[prost(message, optional, tag = "7")]
+    options: Option<MessageOptions>,
+    #[prost(message, repeated, tag = "8")]
+    oneof_decl: Vec<RulesOneof>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct MessageOptions {
+    #[prost(bool, optional, tag = "1071")]
+    disabled: Option<bool>,
+    #[prost(bool, optional, tag = "1072")]
+    ignored: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RulesField {
+    #[prost(message, optional, tag = "8")]
+    options: Option<FieldOptions>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FieldOptions {
+    #[prost(message, optional, tag = "1071")]
+    rules: Option<FieldRules>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RulesOneof {
+    #[prost(message, optional, tag = "2")]
+    options: Option<OneofOptions>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct OneofOptions {
+    #[prost(bool, optional, tag = "1071")]
+    required: Option<bool>,
+}
+
+// The following mirror `validate/validate.proto`.
+
+#[derive(Clone, PartialEq, Message)]
+struct FieldRules {
+    #[prost(message, optional, tag = "17")]
+    message: Option<MessageRules>,
+    #[prost(oneof = "Kind", tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 19, 20, 21, 22")]
+    kind: Option<Kind>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+enum Kind {
+    #[prost(message, tag = "1")]
+    Float(FloatRules),
+    #[prost(message, tag = "2")]
+    Double(DoubleRules),
+    #[prost(message, tag = "3")]
+    Int32(Int32Rules),
+    #[prost(message, tag = "4")]
+    Int64(Int64Rules),
+    #[prost(message, tag = "5")]
+    Uint32(UInt32Rules),
+    #[prost(message, tag = "6")]
+    Uint64(UInt64Rules),
+    #[prost(message, tag = "7")]
+    Sint32(SInt32Rules),
+    #[prost(message, tag = "8")]
+    Sint64(SInt64Rules),
+    #[prost(message, tag = "9")]
+    Fixed32(Fixed32Rules),
+    #[prost(message, tag = "10")]
+    Fixed64(Fixed64Rules),
+    #[prost(message, tag = "11")]
+    Sfixed32(SFixed32Rules),
+    #[prost(message, tag = "12")]
+    Sfixed64(SFixed64Rules),
+    #[prost(message, tag = "13")]
+    Bool(BoolRules),
+    #[prost(message, tag = "14")]
+    String(StringRules),
+    #[prost(message, tag = "15")]
+    Bytes(BytesRules),
+    #[prost(message, tag = "16")]
+    Enum(EnumRules),
+    #[prost(message, tag = "18")]
+    Repeated(RepeatedRules),
+    #[prost(message, tag = "19")]
+    Map(MapRules),
+    #[prost(message, tag = "20")]
+    Any(AnyRules),
+    #[prost(message, tag = "21")]
+    Duration(DurationRules),
+    #[prost(message, tag = "22")]
+    Timestamp(TimestampRules),
+}
+
+/// A comparison operand, as a Rust literal and as shown in violations.
+struct Bound {
+    key: f64,
+    literal: String,
+    display: String,
+}
+
+/// Numeric rules with their operands already converted to bounds.
+struct NumericRules {
+    r#const: Option<Bound>,
+    lower: Option<(Bound, bool)>,
+    upper: Option<(Bound, bool)>,
+    r#in: Vec<Bound>,
+    not_in: Vec<Bound>,
+    ignore_empty: bool,
+}
+
+macro_rules! numeric_rules {
+    ($name:ident, $kind:ident, $ty:ty) => {
+        #[derive(Clone, PartialEq, Message)]
+        struct $name {
+            #[prost($kind, optional, tag = "1")]
+            r#const: Option<$ty>,
+            #[prost($kind, optional, tag = "2")]
+            lt: Option<$ty>,
+            #[prost($kind, optional, tag = "3")]
+            lte: Option<$ty>,
+            #[prost($kind, optional, tag = "4")]
+            gt: Option<$ty>,
+            #[prost($kind, optional, tag = "5")]
+            gte: Option<$ty>,
+            #[prost($kind, repeated, packed = "false", tag = "6")]
+            r#in: Vec<$ty>,
+            #[prost($kind, repeated, packed = "false", tag = "7")]
+            not_in: Vec<$ty>,
+            #[prost(bool, optional, tag = "8")]
+            ignore_empty: Option<bool>,
+        }
+
+        impl $name {
+            fn to_numeric(&self) -> NumericRules {
+                let bound = |x: &$ty| Bound {
+                    key: *x as f64,
+                    literal: format!("{x:?}{}", stringify!($ty)),
+                    display: x.to_string(),
+                };
+
+                // Inclusive bounds at the limits of the type always hold.
+                let gte = self.gte.filter(|x| *x != <$ty>::MIN);
+                let lte = self.lte.filter(|x| *x != <$ty>::MAX);
+
+                NumericRules {
+                    r#const: self.r#const.as_ref().map(bound),
+                    lower: get_bound(self.gt.as_ref().map(bound), gte.as_ref().map(bound)),
+                    upper: get_bound(self.lt.as_ref().map(bound), lte.as_ref().map(bound)),
+                    r#in: self.r#in.iter().map(bound).collect(),
+                    not_in: self.not_in.iter().map(bound).collect(),
+                    ignore_empty: self.ignore_empty(),
+                }
+            }
+        }
+    };
+}
+
+numeric_rules!(FloatRules, float, f32);
+numeric_rules!(DoubleRules, double, f64);
+numeric_rules!(Int32Rules, int32, i32);
+numeric_rules!(Int64Rules, int64, i64);
+numeric_rules!(UInt32Rules, uint32, u32);
+numeric_rules!(UInt64Rules, uint64, u64);
+numeric_rules!(SInt32Rules, sint32, i32);
+numeric_rules!(SInt64Rules, sint64, i64);
+numeric_rules!(Fixed32Rules, fixed32, u32);
+numeric_rules!(Fixed64Rules, fixed64, u64);
+numeric_rules!(SFixed32Rules, sfixed32, i32);
+numeric_rules!(SFixed64Rules, sfixed64, i64);
+
+#[derive(Clone, PartialEq, Message)]
+struct BoolRules {
+    #[prost(bool, optional, tag = "1")]
+    r#const: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct StringRules {
+    #[prost(string, optional, tag = "1")]
+    r#const: Option<String>,
+    #[prost(uint64, optional, tag = "19")]
+    len: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    min_len: Option<u64>,
+    #[prost(uint64, optional, tag = "3")]
+    max_len: Option<u64>,
+    #[prost(uint64, optional, tag = "20")]
+    len_bytes: Option<u64>,
+    #[prost(uint64, optional, tag = "4")]
+    min_bytes: Option<u64>,
+    #[prost(uint64, optional, tag = "5")]
+    max_bytes: Option<u64>,
+    #[prost(string, optional, tag = "6")]
+    pattern: Option<String>,
+    #[prost(string, optional, tag = "7")]
+    prefix: Option<String>,
+    #[prost(string, optional, tag = "8")]
+    suffix: Option<String>,
+    #[prost(string, optional, tag = "9")]
+    contains: Option<String>,
+    #[prost(string, optional, tag = "23")]
+    not_contains: Option<String>,
+    #[prost(string, repeated, tag = "10")]
+    r#in: Vec<String>,
+    #[prost(string, repeated, tag = "11")]
+    not_in: Vec<String>,
+    #[prost(oneof = "WellKnown", tags = "12, 13, 14, 15, 16, 17, 18, 21, 22, 24")]
+    well_known: Option<WellKnown>,
+    #[prost(bool, optional, tag = "25", default = "true")]
+    strict: Option<bool>,
+    #[prost(bool, optional, tag = "26")]
+    ignore_empty: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+enum WellKnown {
+    #[prost(bool, tag = "12")]
+    Email(bool),
+    #[prost(bool, tag = "13")]
+    Hostname(bool),
+    #[prost(bool, tag = "14")]
+    Ip(bool),
+    #[prost(bool, tag = "15")]
+    Ipv4(bool),
+    #[prost(bool, tag = "16")]
+    Ipv6(bool),
+    #[prost(bool, tag = "17")]
+    Uri(bool),
+    #[prost(bool, tag = "18")]
+    UriRef(bool),
+    #[prost(bool, tag = "21")]
+    Address(bool),
+    #[prost(bool, tag = "22")]
+    Uuid(bool),
+    #[prost(int32, tag = "24")]
+    KnownRegex(i32),
+}
+
+const HTTP_HEADER_NAME: i32 = 1;
+const HTTP_HEADER_VALUE: i32 = 2;
+
+#[derive(Clone, PartialEq, Message)]
+struct BytesRules {
+    #[prost(bytes = "vec", optional, tag = "1")]
+    r#const: Option<Vec<u8>>,
+    #[prost(uint64, optional, tag = "13")]
+    len: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    min_len: Option<u64>,
+    #[prost(uint64, optional, tag = "3")]
+    max_len: Option<u64>,
+    #[prost(string, optional, tag = "4")]
+    pattern: Option<String>,
+    #[prost(bytes = "vec", optional, tag = "5")]
+    prefix: Option<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "6")]
+    suffix: Option<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "7")]
+    contains: Option<Vec<u8>>,
+    #[prost(bytes = "vec", repeated, tag = "8")]
+    r#in: Vec<Vec<u8>>,
+    #[prost(bytes = "vec", repeated, tag = "9")]
+    not_in: Vec<Vec<u8>>,
+    #[prost(bool, optional, tag = "10")]
+    ip: Option<bool>,
+    #[prost(bool, optional, tag = "11")]
+    ipv4: Option<bool>,
+    #[prost(bool, optional, tag = "12")]
+    ipv6: Option<bool>,
+    #[prost(bool, optional, tag = "14")]
+    ignore_empty: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct EnumRules {
+    #[prost(int32, optional, tag = "1")]
+    r#const: Option<i32>,
+    #[prost(bool, optional, tag = "2")]
+    defined_only: Option<bool>,
+    #[prost(int32, repeated, packed = "false", tag = "3")]
+    r#in: Vec<i32>,
+    #[prost(int32, repeated, packed = "false", tag = "4")]
+    not_in: Vec<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct MessageRules {
+    #[prost(bool, optional, tag = "1")]
+    skip: Option<bool>,
+    #[prost(bool, optional, tag = "2")]
+    required: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RepeatedRules {
+    #[prost(uint64, optional, tag = "1")]
+    min_items: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    max_items: Option<u64>,
+    #[prost(bool, optional, tag = "3")]
+    unique: Option<bool>,
+    #[prost(message, optional, boxed, tag = "4")]
+    items: Option<Box<FieldRules>>,
+    #[prost(bool, optional, tag = "5")]
+    ignore_empty: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct MapRules {
+    #[prost(uint64, optional, tag = "1")]
+    min_pairs: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    max_pairs: Option<u64>,
+    #[prost(message, optional, boxed, tag = "4")]
+    keys: Option<Box<FieldRules>>,
+    #[prost(message, optional, boxed, tag = "5")]
+    values: Option<Box<FieldRules>>,
+    #[prost(bool, optional, tag = "6")]
+    ignore_empty: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct AnyRules {
+    #[prost(bool, optional, tag = "1")]
+    required: Option<bool>,
+    #[prost(string, repeated, tag = "2")]
+    r#in: Vec<String>,
+    #[prost(string, repeated, tag = "3")]
+    not_in: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct DurationValue {
+    #[prost(int64, tag = "1")]
+    seconds: i64,
+    #[prost(int32, tag = "2")]
+    nanos: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct DurationRules {
+    #[prost(bool, optional, tag = "1")]
+    required: Option<bool>,
+    #[prost(message, optional, tag = "2")]
+    r#const: Option<DurationValue>,
+    #[prost(message, optional, tag = "3")]
+    lt: Option<DurationValue>,
+    #[prost(message, optional, tag = "4")]
+    lte: Option<DurationValue>,
+    #[prost(message, optional, tag = "5")]
+    gt: Option<DurationValue>,
+    #[prost(message, optional, tag = "6")]
+    gte: Option<DurationValue>,
+    #[prost(message, repeated, tag = "7")]
+    r#in: Vec<DurationValue>,
+    #[prost(message, repeated, tag = "8")]
+    not_in: Vec<DurationValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TimestampRules {
+    #[prost(bool, optional, tag = "1")]
+    required: Option<bool>,
+    #[prost(message, optional, tag = "2")]
+    r#const: Option<DurationValue>,
+    #[prost(message, optional, tag = "3")]
+    lt: Option<DurationValue>,
+    #[prost(message, optional, tag = "4")]
+    lte: Option<DurationValue>,
+    #[prost(message, optional, tag = "5")]
+    gt: Option<DurationValue>,
+    #[prost(message, optional, tag = "6")]
+    gte: Option<DurationValue>,
+    #[prost(bool, optional, tag = "7")]
+    lt_now: Option<bool>,
+    #[prost(bool, optional, tag = "8")]
+    gt_now: Option<bool>,
+    #[prost(message, optional, tag = "9")]
+    within: Option<DurationValue>,
+}
+
+fn get_bound(exclusive: Option<Bound>, inclusive: Option<Bound>) -> Option<(Bound, bool)> {
+    match (exclusive, inclusive) {
+        (Some(bound), _) => Some((bound, false)),
+        (None, Some(bound)) => Some((bound, true)),
+        (None, None) => None,
+    }
+}
+
+fn format_duration(value: &DurationValue) -> String {
+    match value.nanos {
+        0 => format!("{}s", value.seconds),
+        nanos => format!("{}.{}s", value.seconds, format!("{:09}", nanos.abs()).trim_end_matches('0')),
+    }
+}
+
+fn get_duration_bound(value: &DurationValue) -> Bound {
+    Bound {
+        key: value.seconds as f64 + value.nanos as f64 / 1e9,
+        literal: format!("({}i64, {}i32)", value.seconds, value.nanos),
+        display: format_duration(value),
+    }
+}
+
+fn get_timestamp_bound(value: &DurationValue) -> Bound {
+    Bound {
+        display: format!("{} after the Unix epoch", format_duration(value)),
+        ..get_duration_bound(value)
+    }
+}
+
+fn get_bytes_literal(value: &[u8]) -> String {
+    format!("b\"{}\"", value.escape_ascii())
+}
+
+fn get_list_display(values: &[String]) -> String {
+    format!("[{}]", values.join(", "))
+}
+
+/// The type a field value is bound to in the generated code. Messages and
+/// enums carry their fully qualified name without the leading dot.
+#[derive(Clone, Copy)]
+enum ValueType<'a> {
+    Scalar(Type),
+    Enum(&'a str),
+    Message(&'a str),
+}
+
+impl<'a> ValueType<'a> {
+    fn from_field(field: &'a FieldDescriptorProto) -> ValueType<'a> {
+        let type_name = field.type_name().trim_start_matches('.');
+
+        match field.r#type() {
+            Type::Message | Type::Group => ValueType::Message(type_name),
+            Type::Enum => ValueType::Enum(type_name),
+            scalar => ValueType::Scalar(scalar),
+        }
+    }
+
+    /// Returns the scalar type the value holds, looking through the
+    /// well-known wrapper types, whose rules apply to the wrapped value.
+    fn get_scalar_type(&self) -> Option<Type> {
+        match self {
+            ValueType::Scalar(scalar) => Some(*scalar),
+            ValueType::Message("google.protobuf.DoubleValue") => Some(Type::Double),
+            ValueType::Message("google.protobuf.FloatValue") => Some(Type::Float),
+            ValueType::Message("google.protobuf.Int64Value") => Some(Type::Int64),
+            ValueType::Message("google.protobuf.UInt64Value") => Some(Type::Uint64),
+            ValueType::Message("google.protobuf.Int32Value") => Some(Type::Int32),
+            ValueType::Message("google.protobuf.UInt32Value") => Some(Type::Uint32),
+            ValueType::Message("google.protobuf.BoolValue") => Some(Type::Bool),
+            ValueType::Message("google.protobuf.StringValue") => Some(Type::String),
+            ValueType::Message("google.protobuf.BytesValue") => Some(Type::Bytes),
+            _ => None,
+        }
+    }
+}
+
+fn indent(lines: Vec<String>) -> impl Iterator<Item = String> {
+    lines.into_iter().map(|x| format!("    {x}"))
+}
+
+fn write_block(lines: &mut Vec<String>, open: &str, body: Vec<String>) {
+    match open.is_empty() {
+        true => lines.push("{".to_string()),
+        false => lines.push(format!("{open} {{")),
+    }
+    lines.extend(indent(body));
+    lines.push("}".to_string());
+}
+
+fn write_check(lines: &mut Vec<String>, condition: &str, message: &str) {
+    write_block(lines, &format!("if {condition}"), vec![
+        format!("violations.push(crate::validation::Violation::new(&path, {message:?}));"),
+    ]);
+}
+
+/// Skips the checks when the value is empty, for rules with `ignore_empty`.
+fn write_ignore_empty(lines: Vec<String>, ignore_empty: bool, condition: &str) -> Vec<String> {
+    match ignore_empty && !lines.is_empty() {
+        true => {
+            let mut wrapped = vec![];
+            write_block(&mut wrapped, &format!("if {condition}"), lines);
+            wrapped
+        },
+        false => lines,
+    }
+}
+
+fn write_range_checks(lines: &mut Vec<String>, value: &str, lower: Option<&(Bound, bool)>, upper: Option<&(Bound, bool)>) {
+    let below = |(bound, inclusive): &(Bound, bool)| match inclusive {
+        true => (format!("{value} < {}", bound.literal), format!("greater than or equal to {}", bound.display)),
+        false => (format!("{value} <= {}", bound.literal), format!("greater than {}", bound.display)),
+    };
+    let above = |(bound, inclusive): &(Bound, bool)| match inclusive {
+        true => (format!("{value} > {}", bound.literal), format!("less than or equal to {}", bound.display)),
+        false => (format!("{value} >= {}", bound.literal), format!("less than {}", bound.display)),
+    };
+
+    match (lower, upper) {
+        (Some(lower), Some(upper)) => {
+            let (below_condition, below_message) = below(lower);
+            let (above_condition, above_message) = above(upper);
+
+            // An upper bound below the lower one excludes the range between
+            // them instead.
+            match lower.0.key < upper.0.key {
+                true => write_check(
+                    lines,
+                    &format!("{below_condition} || {above_condition}"),
+                    &format!("must be {below_message} and {above_message}"),
+                ),
+                false => write_check(
+                    lines,
+                    &format!("{below_condition} && {above_condition}"),
+                    &format!("must be {below_message} or {above_message}"),
+                ),
+            }
+        },
+        (Some(lower), None) => {
+            let (condition, message) = below(lower);
+            write_check(lines, &condition, &format!("must be {message}"));
+        },
+        (None, Some(upper)) => {
+            let (condition, message) = above(upper);
+            write_check(lines, &condition, &format!("must be {message}"));
+        },
+        (None, None) => {},
+    }
+}
+
+fn write_in_checks(lines: &mut Vec<String>, value: &str, r#in: &[String], not_in: &[String], display: &[String], not_in_display: &[String]) {
+    if !r#in.is_empty() {
+        write_check(
+            lines,
+            &format!("![{}].contains({value})", r#in.join(", ")),
+            &format!("must be in {}", get_list_display(display)),
+        );
+    }
+
+    if !not_in.is_empty() {
+        write_check(
+            lines,
+            &format!("[{}].contains({value})", not_in.join(", ")),
+            &format!("must not be in {}", get_list_display(not_in_display)),
+        );
+    }
+}
+
+fn get_numeric_checks(rules: &NumericRules) -> Vec<String> {
+    let mut lines = vec![];
+
+    if let Some(bound) = &rules.r#const {
+        write_check(&mut lines, &format!("*value != {}", bound.literal), &format!("must equal {}", bound.display));
+    }
+
+    write_range_checks(&mut lines, "*value", rules.lower.as_ref(), rules.upper.as_ref());
+
+    let literals = |x: &[Bound]| x.iter().map(|x| x.literal.clone()).collect::<Vec<_>>();
+    let displays = |x: &[Bound]| x.iter().map(|x| x.display.clone()).collect::<Vec<_>>();
+
+    write_in_checks(
+        &mut lines,
+        "value",
+        &literals(&rules.r#in),
+        &literals(&rules.not_in),
+        &displays(&rules.r#in),
+        &displays(&rules.not_in),
+    );
+
+    write_ignore_empty(lines, rules.ignore_empty, "*value != Default::default()")
+}
+
+fn get_bool_checks(rules: &BoolRules) -> Vec<String> {
+    let mut lines = vec![];
+
+    if let Some(value) = rules.r#const {
+        write_check(&mut lines, &format!("*value != {value}"), &format!("must equal {value}"));
+    }
+
+    lines
+}
+
+fn get_string_checks(rules: &StringRules) -> Vec<String> {
+    let mut lines = vec![];
+
+    if let Some(value) = &rules.r#const {
+        write_check(&mut lines, &format!("value != {value:?}"), &format!("must equal {value:?}"));
+    }
+
+    let lengths = [
+        (rules.len, "value.chars().count() !=", "length must be", "characters"),
+        (rules.min_len.filter(|x| *x > 0), "value.chars().count() <", "length must be at least", "characters"),
+        (rules.max_len, "value.chars().count() >", "length must be at most", "characters"),
+        (rules.len_bytes, "value.len() !=", "length must be", "bytes"),
+        (rules.min_bytes.filter(|x| *x > 0), "value.len() <", "length must be at least", "bytes"),
+        (rules.max_bytes, "value.len() >", "length must be at most", "bytes"),
+    ];
+
+    for (limit, condition, message, unit) in lengths {
+        if let Some(limit) = limit {
+            write_check(&mut lines, &format!("{condition} {limit}"), &format!("{message} {limit} {unit}"));
+        }
+    }
+
+    if let Some(pattern) = &rules.pattern {
+        write_check(
+            &mut lines,
+            &format!("!crate::validation::is_match({pattern:?}, value)"),
+            &format!("must match the pattern {pattern:?}"),
+        );
+    }
+
+    if let Some(prefix) = &rules.prefix {
+        write_check(&mut lines, &format!("!value.starts_with({prefix:?})"), &format!("must start with {prefix:?}"));
+    }
+
+    if let Some(suffix) = &rules.suffix {
+        write_check(&mut lines, &format!("!value.ends_with({suffix:?})"), &format!("must end with {suffix:?}"));
+    }
+
+    if let Some(contains) = &rules.contains {
+        write_check(&mut lines, &format!("!value.contains({contains:?})"), &format!("must contain {contains:?}"));
+    }
+
+    if let Some(not_contains) = &rules.not_contains {
+        write_check(&mut lines, &format!("value.contains({not_contains:?})"), &format!("must not contain {not_contains:?}"));
+    }
+
+    let quoted = |x: &[String]| x.iter().map(|x| format!("{x:?}")).collect::<Vec<_>>();
+
+    write_in_checks(
+        &mut lines,
+        "&value.as_str()",
+        &quoted(&rules.r#in),
+        &quoted(&rules.not_in),
+        &quoted(&rules.r#in),
+        &quoted(&rules.not_in),
+    );
+
+    let well_known = match &rules.well_known {
+        Some(WellKnown::Email(true)) => Some(("is_email(value)", "must be a valid email address")),
+        Some(WellKnown::Hostname(true)) => Some(("is_hostname(value)", "must be a valid hostname")),
+        Some(WellKnown::Ip(true)) => Some(("is_ip(value)", "must be a valid IP address")),
+        Some(WellKnown::Ipv4(true)) => Some(("is_ipv4(value)", "must be a valid IPv4 address")),
+        Some(WellKnown::Ipv6(true)) => Some(("is_ipv6(value)", "must be a valid IPv6 address")),
+        Some(WellKnown::Uri(true)) => Some(("is_uri(value)", "must be a valid absolute URI")),
+        Some(WellKnown::UriRef(true)) => Some(("is_uri_ref(value)", "must be a valid URI reference")),
+        Some(WellKnown::Address(true)) => Some(("is_address(value)", "must be a valid hostname or IP address")),
+        Some(WellKnown::Uuid(true)) => Some(("is_uuid(value)", "must be a valid UUID")),
+        Some(WellKnown::KnownRegex(HTTP_HEADER_NAME)) => match rules.strict() {
+            true => Some(("is_http_header_name(value, true)", "must be a valid HTTP header name")),
+            false => Some(("is_http_header_name(value, false)", "must be a valid HTTP header name")),
+        },
+        Some(WellKnown::KnownRegex(HTTP_HEADER_VALUE)) => match rules.strict() {
+            true => Some(("is_http_header_value(value, true)", "must be a valid HTTP header value")),
+            false => Some(("is_http_header_value(value, false)", "must be a valid HTTP header value")),
+        },
+        _ => None,
+    };
+
+    if let Some((function, message)) = well_known {
+        write_check(&mut lines, &format!("!crate::validation::{function}"), message);
+    }
+
+    write_ignore_empty(lines, rules.ignore_empty(), "!value.is_empty()")
+}
+
+fn get_bytes_checks(rules: &BytesRules) -> Vec<String> {
+    let mut lines = vec![];
+
+    if let Some(value) = &rules.r#const {
+        let literal = get_bytes_literal(value);
+        write_check(&mut lines, &format!("value.as_slice() != {literal}"), &format!("must equal {literal}"));
+    }
+
+    let lengths = [
+        (rules.len, "value.len() !=", "length must be"),
+        (rules.min_len.filter(|x| *x > 0), "value.len() <", "length must be at least"),
+        (rules.max_len, "value.len() >", "length must be at most"),
+    ];
+
+    for (limit, condition, message) in lengths {
+        if let Some(limit) = limit {
+            write_check(&mut lines, &format!("{condition} {limit}"), &format!("{message} {limit} bytes"));
+        }
+    }
+
+    if let Some(pattern) = &rules.pattern {
+        write_check(
+            &mut lines,
+            &format!("!std::str::from_utf8(value).is_ok_and(|x| crate::validation::is_match({pattern:?}, x))"),
+            &format!("must match the pattern {pattern:?}"),
+        );
+    }
+
+    if let Some(prefix) = &rules.prefix {
+        let literal = get_bytes_literal(prefix);
+        write_check(&mut lines, &format!("!value.starts_with({literal})"), &format!("must start with {literal}"));
+    }
+
+    if let Some(suffix) = &rules.suffix {
+        let literal = get_bytes_literal(suffix);
+        write_check(&mut lines, &format!("!value.ends_with({literal})"), &format!("must end with {literal}"));
+    }
+
+    if let Some(contains) = &rules.contains {
+        let literal = get_bytes_literal(contains);
+        write_check(&mut lines, &format!("!crate::validation::contains_bytes(value, {literal})"), &format!("must contain {literal}"));
+    }
+
+    let literals = |x: &[Vec<u8>]| x.iter().map(|x| format!("{}.as_slice()", get_bytes_literal(x))).collect::<Vec<_>>();
+    let displays = |x: &[Vec<u8>]| x.iter().map(|x| get_bytes_literal(x)).collect::<Vec<_>>();
+
+    write_in_checks(
+        &mut lines,
+        "&value.as_slice()",
+        &literals(&rules.r#in),
+        &literals(&rules.not_in),
+        &displays(&rules.r#in),
+        &displays(&rules.not_in),
+    );
+
+    if rules.ip() {
+        write_check(&mut lines, "!matches!(value.len(), 4 | 16)", "must be a valid IP address in byte format");
+    }
+
+    if rules.ipv4() {
+        write_check(&mut lines, "value.len() != 4", "must be a valid IPv4 address in byte format");
+    }
+
+    if rules.ipv6() {
+        write_check(&mut lines, "value.len() != 16", "must be a valid IPv6 address in byte format");
+    }
+
+    write_ignore_empty(lines, rules.ignore_empty(), "!value.is_empty()")
+}
+
+fn get_enum_checks(rules: &EnumRules, rust_path: &str) -> Vec<String> {
+    let mut lines = vec![];
+
+    if let Some(value) = rules.r#const {
+        write_check(&mut lines, &format!("*value != {value}"), &format!("must equal {value}"));
+    }
+
+    if rules.defined_only() {
+        write_check(&mut lines, &format!("{rust_path}::try_from(*value).is_err()"), "must be a defined enum value");
+    }
+
+    let literals = |x: &[i32]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+
+    write_in_checks(
+        &mut lines,
+        "value",
+        &literals(&rules.r#in),
+        &literals(&rules.not_in),
+        &literals(&rules.r#in),
+        &literals(&rules.not_in),
+    );
+
+    lines
+}
+
+fn get_duration_checks(rules: &DurationRules) -> Vec<String> {
+    let mut lines = vec![];
+
+    if let Some(value) = &rules.r#const {
+        let bound = get_duration_bound(value);
+        write_check(&mut lines, &format!("(value.seconds, value.nanos) != {}", bound.literal), &format!("must equal {}", bound.display));
+    }
+
+    write_range_checks(
+        &mut lines,
+        "(value.seconds, value.nanos)",
+        get_bound(rules.gt.as_ref().map(get_duration_bound), rules.gte.as_ref().map(get_duration_bound)).as_ref(),
+        get_bound(rules.lt.as_ref().map(get_duration_bound), rules.lte.as_ref().map(get_duration_bound)).as_ref(),
+    );
+
+    let literals = |x: &[DurationValue]| x.iter().map(|x| get_duration_bound(x).literal).collect::<Vec<_>>();
+    let displays = |x: &[DurationValue]| x.iter().map(format_duration).collect::<Vec<_>>();
+
+    write_in_checks(
+        &mut lines,
+        "&(value.seconds, value.nanos)",
+        &literals(&rules.r#in),
+        &literals(&rules.not_in),
+        &displays(&rules.r#in),
+        &displays(&rules.not_in),
+    );
+
+    lines
+}
+
+fn get_timestamp_checks(rules: &TimestampRules) -> Vec<String> {
+    let mut lines = vec![];
+
+    if let Some(value) = &rules.r#const {
+        let bound = get_timestamp_bound(value);
+        write_check(&mut lines, &format!("(value.seconds, value.nanos) != {}", bound.literal), &format!("must equal {}", bound.display));
+    }
+
+    write_range_checks(
+        &mut lines,
+        "(value.seconds, value.nanos)",
+        get_bound(rules.gt.as_ref().map(get_timestamp_bound), rules.gte.as_ref().map(get_timestamp_bound)).as_ref(),
+        get_bound(rules.lt.as_ref().map(get_timestamp_bound), rules.lte.as_ref().map(get_timestamp_bound)).as_ref(),
+    );
+
+    if rules.lt_now() {
+        write_check(&mut lines, "(value.seconds, value.nanos) >= crate::validation::now()", "must be in the past");
+    }
+
+    if rules.gt_now() {
+        write_check(&mut lines, "(value.seconds, value.nanos) <= crate::validation::now()", "must be in the future");
+    }
+
+    if let Some(within) = &rules.within {
+        write_check(
+            &mut lines,
+            &format!("!crate::validation::is_within(value.seconds, value.nanos, {})", get_duration_bound(within).literal),
+            &format!("must be within {} of now", format_duration(within)),
+        );
+    }
+
+    lines
+}
+
+fn get_any_checks(rules: &AnyRules) -> Vec<String> {
+    let mut lines = vec![];
+    let quoted = |x: &[String]| x.iter().map(|x| format!("{x:?}")).collect::<Vec<_>>();
+
+    if !rules.r#in.is_empty() {
+        write_check(
+            &mut lines,
+            &format!("![{}].contains(&value.type_url.as_str())", quoted(&rules.r#in).join(", ")),
+            &format!("type URL must be in {}", get_list_display(&quoted(&rules.r#in))),
+        );
+    }
+
+    if !rules.not_in.is_empty() {
+        write_check(
+            &mut lines,
+            &format!("[{}].contains(&value.type_url.as_str())", quoted(&rules.not_in).join(", ")),
+            &format!("type URL must not be in {}", get_list_display(&quoted(&rules.not_in))),
+        );
+    }
+
+    lines
+}
+
+/// Returns the checks of scalar rules, or `None` if they do not apply to
+/// the scalar type.
+fn get_scalar_checks(kind: &Kind, scalar: Type) -> Option<Vec<String>> {
+    match (kind, scalar) {
+        (Kind::Float(rules), Type::Float) => Some(get_numeric_checks(&rules.to_numeric())),
+        (Kind::Double(rules), Type::Double) => Some(get_numeric_checks(&rules.to_numeric())),
+        (Kind::Int32(rules), Type::Int32) => Some(get_numeric_checks(&rules.to_numeric())),
+        (Kind::Int64(rules), Type::Int64) => Some(get_numeric_checks(&rules.to_numeric())),
+        (Kind::Uint32(rules), Type::Uint32) => Some(get_numeric_checks(&rules.to_numeric())),
+        (Kind::Uint64(rules), Type::Uint64) => Some(get_numeric_checks(&rules.to_numeric())),
+        (Kind::Sint32(rules), Type::Sint32) => Some(get_numeric_checks(&rules.to_numeric())),
+        (Kind::Sint64(rules), Type::Sint64) => Some(get_numeric_checks(&rules.to_numeric())),
+        (Kind::Fixed32(rules), Type::Fixed32) => Some(get_numeric_checks(&rules.to_numeric())),
+        (Kind::Fixed64(rules), Type::Fixed64) => Some(get_numeric_checks(&rules.to_numeric())),
+        (Kind::Sfixed32(rules), Type::Sfixed32) => Some(get_numeric_checks(&rules.to_numeric())),
+        (Kind::Sfixed64(rules), Type::Sfixed64) => Some(get_numeric_checks(&rules.to_numeric())),
+        (Kind::Bool(rules), Type::Bool) => Some(get_bool_checks(rules)),
+        (Kind::String(rules), Type::String) => Some(get_string_checks(rules)),
+        (Kind::Bytes(rules), Type::Bytes) => Some(get_bytes_checks(rules)),
+        _ => None,
+    }
+}
+
+fn is_required(rules: Option<&FieldRules>) -> bool {
+    let rules = match rules {
+        Some(rules) => rules,
+        None => return false,
+    };
+
+    let required = match &rules.kind {
+        Some(Kind::Any(rules)) => rules.required(),
+        Some(Kind::Duration(rules)) => rules.required(),
+        Some(Kind::Timestamp(rules)) => rules.required(),
+        _ => false,
+    };
+
+    required || rules.message.as_ref().is_some_and(|x| x.required())
+}
+
+/// What the checks of a file's messages depend on besides the messages.
+struct FileScope {
+    /// Path from the package module to the root of the generated modules.
+    root: String,
+    is_proto3: bool,
+}
+
+struct Generator<'a> {
+    enum_paths: HashMap<String, String>,
+    messages: HashMap<String, &'a DescriptorProto>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(descriptors: &'a FileDescriptorSet) -> Generator<'a> {
+        let mut messages = HashMap::new();
+        let mut stack: Vec<(String, &DescriptorProto)> = descriptors.file.iter()
+            .flat_map(|file| file.message_type.iter().map(|x| (file.package().to_string(), x)))
+            .collect();
+
+        while let Some((prefix, message)) = stack.pop() {
+            let full_name = format!("{prefix}.{}", message.name());
+            stack.extend(message.nested_type.iter().map(|x| (full_name.clone(), x)));
+            messages.insert(full_name, message);
+        }
+
+        Generator {
+            enum_paths: get_enum_paths(descriptors),
+            messages,
+        }
+    }
+
+    fn is_map_entry(&self, type_name: &str) -> bool {
+        self.messages.get(type_name)
+            .and_then(|x| x.options.as_ref())
+            .is_some_and(|x| x.map_entry())
+    }
+
+    /// Returns the checks of a value bound to `value`, with its location
+    /// bound to `path`. The caller handles presence and repetition.
+    fn get_value_checks(&self, value_type: ValueType, rules: Option<&FieldRules>, root: &str, location: &str) -> Vec<String> {
+        let mut lines = vec![];
+        let kind = rules.and_then(|x| x.kind.as_ref());
+
+        let scalar_checks = match (kind, value_type.get_scalar_type()) {
+            (Some(kind), Some(scalar)) => get_scalar_checks(kind, scalar),
+            _ => None,
+        };
+
+        match (kind, value_type) {
+            (None, _) => {},
+            (Some(_), ValueType::Message(_)) if scalar_checks.is_some() => {
+                let checks = scalar_checks.unwrap();
+
+                // prost-types has no wrapper types, their fields hold the
+                // wrapped value itself.
+                if get_wkt_crate() == Some("::prost_types") {
+                    lines.extend(checks);
+                } else if !checks.is_empty() {
+                    let mut body = vec!["let value = &value.value;".to_string()];
+                    body.extend(checks);
+                    write_block(&mut lines, "", body);
+                }
+            },
+            (Some(_), _) if scalar_checks.is_some() => lines.extend(scalar_checks.unwrap()),
+            (Some(Kind::Enum(rules)), ValueType::Enum(name)) => {
+                lines.extend(get_enum_checks(rules, &format!("{root}{}", self.enum_paths[name])));
+            },
+            (Some(Kind::Duration(rules)), ValueType::Message("google.protobuf.Duration")) => {
+                lines.extend(get_duration_checks(rules));
+            },
+            (Some(Kind::Timestamp(rules)), ValueType::Message("google.protobuf.Timestamp")) => {
+                lines.extend(get_timestamp_checks(rules));
+            },
+            (Some(Kind::Any(rules)), ValueType::Message("google.protobuf.Any")) => {
+                lines.extend(get_any_checks(rules));
+            },
+            (Some(_), _) => log(&format!("ignoring validation rules of {location}, which do not match its type")),
+        }
+
+        if let ValueType::Message(name) = value_type {
+            let skip = rules.and_then(|x| x.message.as_ref()).is_some_and(|x| x.skip());
+
+            if !skip && !name.starts_with("google.protobuf.") {
+                lines.push("crate::Validate::validate_into(value, &path, violations);".to_string());
+            }
+        }
+
+        lines
+    }
+
+    fn get_map_checks(&self, field: &FieldDescriptorProto, rules: Option<&FieldRules>, root: &str, location: &str) -> Vec<String> {
+        let ident = to_snake(field.name());
+        let entry = self.messages[field.type_name().trim_start_matches('.')];
+        let key_field = entry.field.iter().find(|x| x.number() == 1).unwrap();
+        let value_field = entry.field.iter().find(|x| x.number() == 2).unwrap();
+
+        let (map_rules, key_rules, value_rules) = match rules.and_then(|x| x.kind.as_ref()) {
+            Some(Kind::Map(map_rules)) => (Some(map_rules), map_rules.keys.as_deref(), map_rules.values.as_deref()),
+            _ => (None, None, rules),
+        };
+
+        let mut lines = vec![];
+
+        if let Some(map_rules) = map_rules {
+            if let Some(limit) = map_rules.min_pairs.filter(|x| *x > 0) {
+                write_check(&mut lines, &format!("self.{ident}.len() < {limit}"), &format!("must have at least {limit} pairs"));
+            }
+
+            if let Some(limit) = map_rules.max_pairs {
+                write_check(&mut lines, &format!("self.{ident}.len() > {limit}"), &format!("must have at most {limit} pairs"));
+            }
+        }
+
+        let key_checks = self.get_value_checks(ValueType::from_field(key_field), key_rules, root, location);
+        let value_checks = self.get_value_checks(ValueType::from_field(value_field), value_rules, root, location);
+
+        if !key_checks.is_empty() || !value_checks.is_empty() {
+            let mut body = vec!["let path = format!(\"{path}[{key:?}]\");".to_string()];
+
+            if !key_checks.is_empty() {
+                let mut key_body = vec!["let value = key;".to_string()];
+                key_body.extend(key_checks);
+                write_block(&mut body, "", key_body);
+            }
+
+            let binding = match value_checks.is_empty() {
+                true => "_",
+                false => "value",
+            };
+
+            body.extend(value_checks);
+            write_block(&mut lines, &format!("for (key, {binding}) in &self.{ident}"), body);
+        }
+
+        let ignore_empty = map_rules.is_some_and(|x| x.ignore_empty());
+        write_ignore_empty(lines, ignore_empty, &format!("!self.{ident}.is_empty()"))
+    }
+
+    fn get_repeated_checks(&self, field: &FieldDescriptorProto, rules: Option<&FieldRules>, root: &str, location: &str) -> Vec<String> {
+        let ident = to_snake(field.name());
+
+        let (repeated_rules, item_rules) = match rules.and_then(|x| x.kind.as_ref()) {
+            Some(Kind::Repeated(repeated_rules)) => (Some(repeated_rules), repeated_rules.items.as_deref()),
+            _ => (None, rules),
+        };
+
+        let mut lines = vec![];
+
+        if let Some(repeated_rules) = repeated_rules {
+            if let Some(limit) = repeated_rules.min_items.filter(|x| *x > 0) {
+                write_check(&mut lines, &format!("self.{ident}.len() < {limit}"), &format!("must have at least {limit} items"));
+            }
+
+            if let Some(limit) = repeated_rules.max_items {
+                write_check(&mut lines, &format!("self.{ident}.len() > {limit}"), &format!("must have at most {limit} items"));
+            }
+
+            if repeated_rules.unique() {
+                write_check(&mut lines, &format!("crate::validation::has_duplicates(&self.{ident})"), "must contain unique items");
+            }
+        }
+
+        let item_checks = self.get_value_checks(ValueType::from_field(field), item_rules, root, location);
+
+        if !item_checks.is_empty() {
+            let mut body = vec!["let path = format!(\"{path}[{index}]\");".to_string()];
+            body.extend(item_checks);
+            write_block(&mut lines, &format!("for (index, value) in self.{ident}.iter().enumerate()"), body);
+        }
+
+        let ignore_empty = repeated_rules.is_some_and(|x| x.ignore_empty());
+        write_ignore_empty(lines, ignore_empty, &format!("!self.{ident}.is_empty()"))
+    }
+
+    fn get_field_checks(
+        &self,
+        message: &DescriptorProto,
+        rust_module: &str,
+        file: &FileScope,
+        field: &FieldDescriptorProto,
+        rules: Option<&FieldRules>,
+        location: &str,
+    ) -> Vec<String> {
+        let ident = to_snake(field.name());
+        let value_type = ValueType::from_field(field);
+        let path = format!("let path = crate::validation::join(path, {:?});", field.name());
+        let mut lines = vec![];
+
+        if field.label() == Label::Repeated {
+            let checks = match self.is_map_entry(field.type_name().trim_start_matches('.')) {
+                true => self.get_map_checks(field, rules, &file.root, location),
+                false => self.get_repeated_checks(field, rules, &file.root, location),
+            };
+
+            if !checks.is_empty() {
+                let mut body = vec![path];
+                body.extend(checks);
+                write_block(&mut lines, "", body);
+            }
+
+            return lines;
+        }
+
+        let checks = self.get_value_checks(value_type, rules, &file.root, location);
+
+        match field.oneof_index {
+            Some(index) if !field.proto3_optional() => {
+                if !checks.is_empty() {
+                    let oneof = &message.oneof_decl[index as usize];
+                    let mut body = vec![path];
+                    body.extend(checks);
+                    write_block(&mut lines, &format!(
+                        "if let Some({rust_module}::{}::{}(value)) = &self.{}",
+                        to_upper_camel(oneof.name()),
+                        to_upper_camel(field.name()),
+                        to_snake(oneof.name()),
+                    ), body);
+                }
+
+                return lines;
+            },
+            _ => {},
+        }
+
+        let is_optional = match value_type {
+            ValueType::Message(_) => true,
+            _ => field.proto3_optional() || field.label() == Label::Optional && !file.is_proto3,
+        };
+
+        match (is_optional, is_required(rules)) {
+            (true, true) if checks.is_empty() => {
+                write_block(&mut lines, &format!("if self.{ident}.is_none()"), vec![
+                    format!("violations.push(crate::validation::Violation::new(&crate::validation::join(path, {:?}), \"is required\"));", field.name()),
+                ]);
+            },
+            (true, true) => {
+                let mut body = vec![path];
+                body.extend(checks);
+
+                let mut arms = vec![];
+                write_block(&mut arms, "Some(value) =>", body);
+                arms.push(format!(
+                    "None => violations.push(crate::validation::Violation::new(&crate::validation::join(path, {:?}), \"is required\")),",
+                    field.name(),
+                ));
+
+                write_block(&mut lines, &format!("match &self.{ident}"), arms);
+            },
+            (true, false) if !checks.is_empty() => {
+                let mut body = vec![path];
+                body.extend(checks);
+                write_block(&mut lines, &format!("if let Some(value) = &self.{ident}"), body);
+            },
+            (false, _) if !checks.is_empty() => {
+                let mut body = vec![path, format!("let value = &self.{ident};")];
+                body.extend(checks);
+                write_block(&mut lines, "", body);
+            },
+            _ => {},
+        }
+
+        lines
+    }
+
+    fn write_message(
+        &self,
+        buffer: &mut Vec<u8>,
+        proto_prefix: &str,
+        rust_prefix: &str,
+        file: &FileScope,
+        message: &DescriptorProto,
+        rules: &RulesMessage,
+    ) {
+        if message.options.as_ref().is_some_and(|x| x.map_entry()) {
+            return;
+        }
+
+        let full_name = format!("{proto_prefix}{}", message.name());
+        let rust_path = format!("{rust_prefix}{}", to_upper_camel(message.name()));
+        let rust_module = format!("{rust_prefix}{}", to_snake(message.name()));
+        let options = rules.options.clone().unwrap_or_default();
+        let mut lines = vec![];
+
+        if !options.disabled() && !options.ignored() {
+            for (field, field_rules) in message.field.iter().zip(&rules.field) {
+                let field_rules = field_rules.options.as_ref().and_then(|x| x.rules.as_ref());
+                let location = format!("{full_name}.{}", field.name());
+                lines.extend(self.get_field_checks(message, &rust_module, file, field, field_rules, &location));
+            }
+
+            for (index, (oneof, oneof_rules)) in message.oneof_decl.iter().zip(&rules.oneof_decl).enumerate() {
+                let required = oneof_rules.options.as_ref().is_some_and(|x| x.required());
+                let synthetic = message.field.iter()
+                    .any(|x| x.oneof_index == Some(index as i32) && x.proto3_optional());
+
+                if required && !synthetic {
+                    write_block(&mut lines, &format!("if self.{}.is_none()", to_snake(oneof.name())), vec![format!(
+                        "violations.push(crate::validation::Violation::new(&crate::validation::join(path, {:?}), \"is required\"));",
+                        oneof.name(),
+                    )]);
+                }
+            }
+        }
+
+        write_line(buffer, 0, &format!("impl crate::Validate for {rust_path} {{"));
+
+        match lines.is_empty() {
+            true => write_line(buffer, 1, "fn validate_into(&self, _path: &str, _violations: &mut Vec<crate::validation::Violation>) {}"),
+            false => {
+                write_line(buffer, 1, "fn validate_into(&self, path: &str, violations: &mut Vec<crate::validation::Violation>) {");
+
+                for line in &lines {
+                    write_line(buffer, 2, line);
+                }
+
+                write_line(buffer, 1, "}");
+            },
+        }
+
+        write_line(buffer, 0, "}");
+
+        for (nested, nested_rules) in message.nested_type.iter().zip(&rules.nested_type) {
+            self.write_message(
+                buffer,
+                &format!("{full_name}."),
+                &format!("{rust_module}::"),
+                file,
+                nested,
+                nested_rules,
+            );
+        }
+    }
+}
+
+/// Writes a `<package>.validate.rs` file per package, implementing
+/// `Validate` for its messages from their protoc-gen-validate annotations.
+pub fn write_validate_files(out_dir: &Path, descriptor_bytes: &[u8], descriptors: &FileDescriptorSet) {
+    let rules = RulesSet::decode(descriptor_bytes).unwrap();
+    let generator = Generator::new(descriptors);
+    let mut buffers: HashMap<&str, Vec<u8>> = HashMap::new();
+
+    for (file, file_rules) in descriptors.file.iter().zip(&rules.file) {
+        // Nothing validates into the well-known types, and an external crate
+        // may not have all of them.
+        if file.package() == "google.protobuf" && get_wkt_crate().is_some() {
+            continue;
+        }
+
+        let module = Module::from_protobuf_package_name(file.package());
+        let scope = FileScope {
+            root: "super::".repeat(module.len()),
+            is_proto3: file.syntax() == "proto3",
+        };
+
+        let buffer = buffers.entry(file.package()).or_insert_with(|| {
+            let mut buffer = vec![];
+            write_line(&mut buffer, 0, "// This file is @generated by the envoypb build script.");
+            buffer
+        });
+
+        for (message, message_rules) in file.message_type.iter().zip(&file_rules.message_type) {
+            generator.write_message(
+                buffer,
+                &format!("{}.", file.package()),
+                "self::",
+                &scope,
+                message,
+                message_rules,
+            );
+        }
+    }
+
+    for (package, buffer) in buffers {
+        let module = Module::from_protobuf_package_name(package);
+        let parts: Vec<&str> = module.parts().collect();
+        fs::write(out_dir.join(format!("{}.validate.rs", parts.join("."))), buffer).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+//! Typed packing and unpacking of `google.protobuf.Any`.
+
+use std::error::Error;
+use std::fmt;
+use prost::{DecodeError, Message, Name};
+#[cfg(all(feature = "extern-wkt", feature = "serde"))]
+use pbjson_types::Any as ExternAny;
+#[cfg(all(feature = "extern-wkt", not(feature = "serde")))]
+use prost_types::Any as ExternAny;
+#[cfg(not(feature = "extern-wkt"))]
+use crate::google::protobuf::Any;
+use crate::registry::get_type_name;
+
+/// Error returned when the message in an `Any` cannot be unpacked.
+#[derive(Debug)]
+pub enum AnyError {
+    /// The type URL does not name the requested message type.
+    TypeMismatch { expected: String, actual: String },
+    /// The type URL does not name a message compiled into the crate.
+    UnknownType(String),
+    /// The value is not a valid encoding of the named message type.
+    Decode { type_url: String, source: DecodeError },
+}
+
+impl fmt::Display for AnyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnyError::TypeMismatch { expected, actual } => {
+                write!(f, "type URL mismatch, expected {expected} but found {actual}")
+            },
+            AnyError::UnknownType(type_url) => write!(
+                f,
+                "unknown type URL {type_url}, the message is not compiled into envoypb \
+                or its package feature is disabled",
+            ),
+            AnyError::Decode { type_url, source } => write!(f, "failed to decode {type_url}: {source}"),
+        }
+    }
+}
+
+impl Error for AnyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AnyError::Decode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// `google.protobuf.Any`, which stays envoypb's own with `extern-wkt` so that
+/// it keeps the JSON mapping resolving its message against the type registry.
+/// It converts to and from the `Any` of the external crate.
+#[cfg(feature = "extern-wkt")]
+#[derive(Clone, PartialEq, Message)]
+pub struct Any {
+    #[prost(string, tag = "1")]
+    pub type_url: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub value: Vec<u8>,
+}
+
+#[cfg(feature = "extern-wkt")]
+impl Name for Any {
+    const NAME: &'static str = "Any";
+    const PACKAGE: &'static str = "google.protobuf";
+
+    fn type_url() -> String {
+        "type.googleapis.com/google.protobuf.Any".to_string()
+    }
+}
+
+// The value is `Bytes` in pbjson-types.
+#[cfg(feature = "extern-wkt")]
+#[allow(clippy::useless_conversion)]
+impl From<ExternAny> for Any {
+    fn from(any: ExternAny) -> Any {
+        Any { type_url: any.type_url, value: any.value.into() }
+    }
+}
+
+#[cfg(feature = "extern-wkt")]
+#[allow(clippy::useless_conversion)]
+impl From<Any> for ExternAny {
+    fn from(any: Any) -> ExternAny {
+        ExternAny { type_url: any.type_url, value: any.value.into() }
+    }
+}
+
+// The methods are inherent to envoypb's `Any`, and a trait for the `Any` of
+// prost-types or pbjson-types, which cannot have inherent methods here.
+macro_rules! any_methods {
+    ($($vis:tt)*) => {
+        /// Packs `message` along with its `type.googleapis.com` type URL.
+        $($vis)* fn pack<M: Message + Name>(message: &M) -> Self {
+            Self {
+                type_url: M::type_url(),
+                value: message.encode_to_vec().into(),
+            }
+        }
+
+        /// Unpacks the message as a `M`, checking the type URL first.
+        $($vis)* fn unpack<M: Message + Name + Default>(&self) -> Result<M, AnyError> {
+            if !self.is::<M>() {
+                return Err(AnyError::TypeMismatch {
+                    expected: M::type_url(),
+                    actual: self.type_url.clone(),
+                });
+            }
+
+            M::decode(&self.value[..]).map_err(|source| AnyError::Decode {
+                type_url: self.type_url.clone(),
+                source,
+            })
+        }
+
+        /// Returns whether the packed message is a `M`.
+        $($vis)* fn is<M: Name>(&self) -> bool {
+            get_type_name(&self.type_url) == M::full_name()
+        }
+    };
+}
+
+impl Any {
+    any_methods!(pub);
+}
+
+/// Typed packing and unpacking of the `Any` of the crate the other
+/// well-known types are taken from with `extern-wkt`.
+#[cfg(feature = "extern-wkt")]
+pub trait AnyExt: Sized {
+    /// Packs `message` along with its `type.googleapis.com` type URL.
+    fn pack<M: Message + Name>(message: &M) -> Self;
+
+    /// Unpacks the message as a `M`, checking the type URL first.
+    fn unpack<M: Message + Name + Default>(&self) -> Result<M, AnyError>;
+
+    /// Returns whether the packed message is a `M`.
+    fn is<M: Name>(&self) -> bool;
+}
+
+#[cfg(feature = "extern-wkt")]
+impl AnyExt for ExternAny {
+    any_methods!();
+}
+
+/// The proto3 JSON mapping of `Any`, resolving the packed message through the
+/// type registry so that any message compiled into the crate can appear as
+/// an Envoy `typed_config`.
+#[cfg(feature = "serde")]
+mod json {
+    use serde::de::{Deserializer, Error as _};
+    use serde::ser::{Error as _, SerializeMap, Serializer};
+    use serde::{Deserialize, Serialize};
+    use crate::registry::get_type_name;
+    use crate::TypeRegistry;
+    use super::Any;
+
+    /// Types embedded in an `Any` under a `value` key rather than inline.
+    const SPECIAL_TYPES: [&str; 15] = [
+        "google.protobuf.Any",
+        "google.protobuf.BoolValue",
+        "google.protobuf.BytesValue",
+        "google.protobuf.DoubleValue",
+        "google.protobuf.Duration",
+        "google.protobuf.FloatValue",
+        "google.protobuf.Int32Value",
+        "google.protobuf.Int64Value",
+        "google.protobuf.ListValue",
+        "google.protobuf.StringValue",
+        "google.protobuf.Struct",
+        "google.protobuf.Timestamp",
+        "google.protobuf.UInt32Value",
+        "google.protobuf.UInt64Value",
+        "google.protobuf.Value",
+    ];
+
+    impl Serialize for Any {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            if self.type_url.is_empty() && self.value.is_empty() {
+                return serializer.serialize_map(Some(0))?.end();
+            }
+
+            let name = get_type_name(&self.type_url);
+            let entry = TypeRegistry::global().get(name)
+                .ok_or_else(|| S::Error::custom(format!("unknown type URL {}", self.type_url)))?;
+            let value = (entry.to_json)(&self.value).map_err(S::Error::custom)?;
+
+            let mut map = serializer.serialize_map(None)?;
+            map.serialize_entry("@type", &self.type_url)?;
+
+            match value {
+                serde_json::Value::Object(fields) if !SPECIAL_TYPES.contains(&name) => {
+                    for (key, value) in &fields {
+                        map.serialize_entry(key, value)?;
+                    }
+                },
+                value => map.serialize_entry("value", &value)?,
+            }
+
+            map.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Any {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let mut fields = serde_json::Map::deserialize(deserializer)?;
+
+            let type_url = match fields.remove("@type") {
+                Some(serde_json::Value::String(x)) => x,
+                Some(_) => return Err(D::Error::custom("@type must be a string")),
+                None if fields.is_empty() => return Ok(Any::default()),
+                None => return Err(D::Error::missing_field("@type")),
+            };
+
+            let name = get_type_name(&type_url);
+            let entry = TypeRegistry::global().get(name)
+                .ok_or_else(|| D::Error::custom(format!("unknown type URL {type_url}")))?;
+
+            let json = match SPECIAL_TYPES.contains(&name) {
+                true => fields.remove("value").ok_or_else(|| D::Error::missing_field("value"))?,
+                false => serde_json::Value::Object(fields),
+            };
+
+            let value = (entry.from_json)(json).map_err(D::Error::custom)?;
+
+            Ok(Any { type_url, value })
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use crate::google::protobuf::Any;
+use crate::newest::envoy::config::core::v3::Node;
+use crate::newest::envoy::config::route::v3::VirtualHost;
+use crate::resource::{type_url, XdsResource};
+
+/// The resources of one type in a snapshot, keyed by name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Resources {
+    /// The version reported to clients, which must change whenever any of
+    /// the resources does.
+    pub version: String,
+    pub items: BTreeMap<String, Any>,
+}
+
+/// A consistent set of resources to serve to a node, keyed by type URL.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    resources: BTreeMap<String, Resources>,
+}
+
+impl Snapshot {
+    pub fn new() -> Snapshot {
+        Snapshot::default()
+    }
+
+    /// Sets the resources of type `M` at `version`, replacing any previous
+    /// resources of that type.
+    pub fn with<M: XdsResource>(mut self, version: impl Into<String>, items: impl IntoIterator<Item = M>) -> Snapshot {
+        let items = items.into_iter()
+            .map(|x| (x.resource_name().to_string(), Any::pack(&x)))
+            .collect();
+
+        self.insert(M::type_url(), Resources { version: version.into(), items });
+        self
+    }
+
+    /// Sets the virtual hosts served over VHDS at `version`, replacing any
+    /// previous ones. Each is named `<route_configuration_name>/<domain>` for
+    /// every one of its domains, as Envoy requests them.
+    pub fn with_virtual_hosts(
+        mut self,
+        version: impl Into<String>,
+        route_configuration_name: &str,
+        virtual_hosts: impl IntoIterator<Item = VirtualHost>,
+    ) -> Snapshot {
+        let items = virtual_hosts.into_iter()
+            .flat_map(|host| {
+                let any = Any::pack(&host);
+                host.domains.into_iter()
+                    .map(move |domain| (format!("{route_configuration_name}/{domain}"), any.clone()))
+            })
+            .collect();
+
+        self.insert(type_url::VIRTUAL_HOST, Resources { version: version.into(), items });
+        self
+    }
+
+    /// Sets the resources of the type named by `type_url`, for types without
+    /// an `XdsResource` implementation.
+    pub fn insert(&mut self, type_url: impl Into<String>, resources: Resources) {
+        self.resources.insert(type_url.into(), resources);
+    }
+
+    /// Returns the resources of the type named by `type_url`.
+    pub fn get(&self, type_url: &str) -> Option<&Resources> {
+        self.resources.get(type_url)
+    }
+
+    /// Lists the type URLs of the resources in the snapshot.
+    pub fn type_urls(&self) -> impl Iterator<Item = &str> + '_ {
+        self.resources.keys().map(|x| x.as_str())
+    }
+}
+
+type NodeKey = dyn Fn(&Node) -> String + Send + Sync;
+type SnapshotSender = watch::Sender<Option<Arc<Snapshot>>>;
+
+struct Inner {
+    node_key: Box<NodeKey>,
+    snapshots: Mutex<HashMap<String, SnapshotSender>>,
+}
+
+/// The latest snapshot of every node, shared by the streams serving them.
+///
+/// Snapshots are keyed by node ID, or by the key returned by the function
+/// given to [`SnapshotCache::with_node_key`], e.g. to serve a whole Envoy
+/// cluster from one snapshot. Setting a snapshot pushes it to every stream
+/// open for that key.
+#[derive(Clone)]
+pub struct SnapshotCache {
+    inner: Arc<Inner>,
+}
+
+impl Default for SnapshotCache {
+    fn default() -> SnapshotCache {
+        SnapshotCache::new()
+    }
+}
+
+impl fmt::Debug for SnapshotCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SnapshotCache").field("keys", &self.keys()).finish()
+    }
+}
+
+impl SnapshotCache {
+    /// Returns an empty cache keyed by node ID.
+    pub fn new() -> SnapshotCache {
+        SnapshotCache::with_node_key(|node| node.id.clone())
+    }
+
+    /// Returns an empty cache keyed by `node_key`.
+    pub fn with_node_key(node_key: impl Fn(&Node) -> String + Send + Sync + 'static) -> SnapshotCache {
+        SnapshotCache {
+            inner: Arc::new(Inner {
+                node_key: Box::new(node_key),
+                snapshots: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Returns the key the snapshot of `node` is stored under.
+    pub fn node_key(&self, node: &Node) -> String {
+        (self.inner.node_key)(node)
+    }
+
+    /// Sets the snapshot of `key` and pushes it to the streams serving it.
+    pub fn set_snapshot(&self, key: impl Into<String>, snapshot: Snapshot) {
+        let mut snapshots = self.inner.snapshots.lock().unwrap();
+        let sender = snapshots.entry(key.into()).or_insert_with(|| watch::channel(None).0);
+        sender.send_replace(Some(Arc::new(snapshot)));
+    }
+
+    /// Returns the snapshot of `key`, if one is set.
+    pub fn snapshot(&self, key: &str) -> Option<Arc<Snapshot>> {
+        let snapshots = self.inner.snapshots.lock().unwrap();
+        snapshots.get(key).and_then(|x| x.borrow().clone())
+    }
+
+    /// Removes the snapshot of `key`. Open streams keep the resources they
+    /// were sent and get nothing more until a new snapshot is set.
+    pub fn clear_snapshot(&self, key: &str) {
+        let mut snapshots = self.inner.snapshots.lock().unwrap();
+
+        if let Some(sender) = snapshots.get(key) {
+            match sender.receiver_count() {
+                0 => {
+                    snapshots.remove(key);
+                },
+                _ => {
+                    sender.send_replace(None);
+                },
+            }
+        }
+    }
+
+    /// Lists the keys that have a snapshot.
+    pub fn keys(&self) -> Vec<String> {
+        let snapshots = self.inner.snapshots.lock().unwrap();
+        snapshots.iter()
+            .filter(|(_, sender)| sender.borrow().is_some())
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Subscribes to the snapshots of `key`, starting with the current one.
+    pub(crate) fn watch(&self, key: &str) -> watch::Receiver<Option<Arc<Snapshot>>> {
+        let mut snapshots = self.inner.snapshots.lock().unwrap();
+        let sender = snapshots.entry(key.to_string()).or_insert_with(|| watch::channel(None).0);
+        sender.subscribe()
+    }
+}
@@ -0,0 +1,398 @@
+//! Checks of the references between the resources of a snapshot.
+//!
+//! Envoy waits for the resources a listener or cluster references before
+//! using it, so a listener whose route configuration is missing from the
+//! snapshot stays warming forever without any error. The check follows the
+//! references into the typed configs of the HTTP connection manager, the
+//! TCP proxy and the TLS transport sockets when their package features are
+//! enabled.
+
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+use prost::{Message, Name};
+use crate::google::protobuf::Any;
+use crate::newest::envoy::config::cluster::v3::Cluster;
+use crate::newest::envoy::config::cluster::v3::cluster::{ClusterDiscoveryType, DiscoveryType};
+use crate::newest::envoy::config::core::v3::{ConfigSource, TransportSocket, TypedExtensionConfig};
+use crate::newest::envoy::config::core::v3::config_source::ConfigSourceSpecifier;
+use crate::newest::envoy::config::endpoint::v3::ClusterLoadAssignment;
+use crate::newest::envoy::config::listener::v3::Listener;
+use crate::newest::envoy::config::listener::v3::filter::ConfigType;
+use crate::newest::envoy::config::route::v3::{RouteConfiguration, ScopedRouteConfiguration};
+use crate::newest::envoy::config::route::v3::route::Action;
+use crate::newest::envoy::config::route::v3::route_action::ClusterSpecifier;
+#[cfg(feature = "extensions-filters-network")]
+use crate::newest::envoy::extensions::filters::network::http_connection_manager::v3::{
+    http_connection_manager::RouteSpecifier, scoped_routes::ConfigSpecifier, HttpConnectionManager,
+};
+#[cfg(feature = "extensions-filters-network")]
+use crate::newest::envoy::extensions::filters::network::tcp_proxy::v3::{tcp_proxy, TcpProxy};
+#[cfg(feature = "extensions-transport-sockets")]
+use crate::newest::envoy::config::core::v3::transport_socket;
+#[cfg(feature = "extensions-transport-sockets")]
+use crate::newest::envoy::extensions::transport_sockets::tls::v3::{
+    common_tls_context::ValidationContextType, downstream_tls_context::SessionTicketKeysType, CommonTlsContext,
+    DownstreamTlsContext, SdsSecretConfig, Secret, UpstreamTlsContext,
+};
+use super::cache::Snapshot;
+
+/// A resource of a snapshot, by type URL and name.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ResourceRef {
+    pub type_url: String,
+    pub name: String,
+}
+
+impl ResourceRef {
+    fn new<M: Name>(name: &str) -> ResourceRef {
+        ResourceRef {
+            type_url: M::type_url(),
+            name: name.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ResourceRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let type_name = self.type_url.rsplit(['/', '.']).next().unwrap_or_default();
+        write!(f, "{type_name} {:?}", self.name)
+    }
+}
+
+/// A problem with the references between the resources of a snapshot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// `from` references `to`, which the snapshot does not contain.
+    Dangling { from: ResourceRef, to: ResourceRef },
+    /// No resource of the snapshot references the resource, which Envoy will
+    /// then never ask for.
+    Unused(ResourceRef),
+    /// The resource, or a typed config in it, could not be decoded.
+    Decode { resource: ResourceRef, message: String },
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Inconsistency::Dangling { from, to } => write!(f, "{from} references {to}, which is not in the snapshot"),
+            Inconsistency::Unused(resource) => write!(f, "{resource} is not referenced by any resource"),
+            Inconsistency::Decode { resource, message } => write!(f, "{resource} cannot be decoded: {message}"),
+        }
+    }
+}
+
+/// Error returned when the resources of a snapshot are inconsistent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConsistencyError {
+    pub inconsistencies: Vec<Inconsistency>,
+}
+
+impl fmt::Display for ConsistencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} inconsistency(ies)", self.inconsistencies.len())?;
+
+        for inconsistency in &self.inconsistencies {
+            write!(f, "\n  {inconsistency}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for ConsistencyError {}
+
+/// Returns whether resources from `source` come over xDS, and so from the
+/// snapshot, rather than from the filesystem.
+fn is_xds(source: Option<&ConfigSource>) -> bool {
+    matches!(
+        source.and_then(|x| x.config_source_specifier.as_ref()),
+        Some(ConfigSourceSpecifier::ApiConfigSource(_) | ConfigSourceSpecifier::Ads(_) | ConfigSourceSpecifier::Self_(_)),
+    )
+}
+
+/// Collects the references of the resources of a snapshot.
+#[derive(Default)]
+struct Graph {
+    /// The resource the references are currently collected from.
+    from: Option<ResourceRef>,
+    references: BTreeSet<(ResourceRef, ResourceRef)>,
+    /// Types whose every resource is referenced, e.g. by a wildcard
+    /// subscription.
+    wildcards: BTreeSet<String>,
+    errors: Vec<Inconsistency>,
+}
+
+impl Graph {
+    fn from(&self) -> &ResourceRef {
+        self.from.as_ref().expect("set while collecting")
+    }
+
+    fn reference<M: Name>(&mut self, name: &str) {
+        self.references.insert((self.from().clone(), ResourceRef::new::<M>(name)));
+    }
+
+    fn decode<M: Message + Name + Default>(&mut self, any: &Any) -> Option<M> {
+        match any.unpack::<M>() {
+            Ok(message) => Some(message),
+            Err(error) => {
+                let resource = self.from().clone();
+                self.errors.push(Inconsistency::Decode { resource, message: error.to_string() });
+                None
+            },
+        }
+    }
+
+    /// Decodes the resources of type `M` and collects their references with
+    /// `collect`.
+    fn collect<M: Message + Name + Default>(&mut self, snapshot: &Snapshot, collect: fn(&mut Graph, &M)) {
+        let Some(resources) = snapshot.get(&M::type_url()) else {
+            return;
+        };
+
+        for (name, item) in &resources.items {
+            self.from = Some(ResourceRef::new::<M>(name));
+
+            if let Some(message) = self.decode::<M>(item) {
+                collect(self, &message);
+            }
+        }
+
+        self.from = None;
+    }
+
+    fn listener(&mut self, listener: &Listener) {
+        for chain in listener.filter_chains.iter().chain(&listener.default_filter_chain) {
+            for filter in &chain.filters {
+                if let Some(ConfigType::TypedConfig(config)) = &filter.config_type {
+                    self.network_filter(config);
+                }
+            }
+
+            self.transport_socket(chain.transport_socket.as_ref());
+        }
+    }
+
+    /// Network filters are also served on their own, as extension configs.
+    fn extension_config(&mut self, extension: &TypedExtensionConfig) {
+        if let Some(config) = &extension.typed_config {
+            self.network_filter(config);
+        }
+    }
+
+    fn network_filter(&mut self, config: &Any) {
+        #[cfg(feature = "extensions-filters-network")]
+        if config.is::<HttpConnectionManager>() {
+            if let Some(manager) = self.decode::<HttpConnectionManager>(config) {
+                self.http_connection_manager(&manager);
+            }
+        } else if config.is::<TcpProxy>() {
+            if let Some(proxy) = self.decode::<TcpProxy>(config) {
+                self.tcp_proxy(&proxy);
+            }
+        }
+
+        let _ = config;
+    }
+
+    #[cfg(feature = "extensions-filters-network")]
+    fn http_connection_manager(&mut self, manager: &HttpConnectionManager) {
+        match &manager.route_specifier {
+            Some(RouteSpecifier::Rds(rds)) if is_xds(rds.config_source.as_ref()) => {
+                self.reference::<RouteConfiguration>(&rds.route_config_name);
+            },
+            Some(RouteSpecifier::RouteConfig(config)) => self.route_configuration(config),
+            Some(RouteSpecifier::ScopedRoutes(scoped)) => match &scoped.config_specifier {
+                Some(ConfigSpecifier::ScopedRouteConfigurationsList(list)) => {
+                    for config in &list.scoped_route_configurations {
+                        self.scoped_route_configuration(config);
+                    }
+                },
+                // Scoped route configurations are subscribed to by wildcard.
+                Some(ConfigSpecifier::ScopedRds(rds)) if is_xds(rds.scoped_rds_config_source.as_ref()) => {
+                    self.wildcards.insert(ScopedRouteConfiguration::type_url());
+                },
+                _ => {},
+            },
+            _ => {},
+        }
+    }
+
+    #[cfg(feature = "extensions-filters-network")]
+    fn tcp_proxy(&mut self, proxy: &TcpProxy) {
+        match &proxy.cluster_specifier {
+            Some(tcp_proxy::ClusterSpecifier::Cluster(name)) => self.reference::<Cluster>(name),
+            Some(tcp_proxy::ClusterSpecifier::WeightedClusters(weighted)) => {
+                for cluster in &weighted.clusters {
+                    self.reference::<Cluster>(&cluster.name);
+                }
+            },
+            None => {},
+        }
+    }
+
+    fn route_configuration(&mut self, config: &RouteConfiguration) {
+        let actions = config.virtual_hosts.iter()
+            .flat_map(|x| &x.routes)
+            .filter_map(|x| match &x.action {
+                Some(Action::Route(action)) => Some(action),
+                _ => None,
+            });
+
+        for action in actions {
+            match &action.cluster_specifier {
+                Some(ClusterSpecifier::Cluster(name)) => self.reference::<Cluster>(name),
+                Some(ClusterSpecifier::WeightedClusters(weighted)) => {
+                    // Weighted clusters may be picked by header instead.
+                    for cluster in weighted.clusters.iter().filter(|x| !x.name.is_empty()) {
+                        self.reference::<Cluster>(&cluster.name);
+                    }
+                },
+                _ => {},
+            }
+
+            for policy in action.request_mirror_policies.iter().filter(|x| !x.cluster.is_empty()) {
+                self.reference::<Cluster>(&policy.cluster);
+            }
+        }
+    }
+
+    fn scoped_route_configuration(&mut self, config: &ScopedRouteConfiguration) {
+        if !config.route_configuration_name.is_empty() {
+            self.reference::<RouteConfiguration>(&config.route_configuration_name);
+        }
+    }
+
+    fn cluster(&mut self, cluster: &Cluster) {
+        let is_eds = cluster.cluster_discovery_type == Some(ClusterDiscoveryType::Type(DiscoveryType::Eds as i32));
+
+        if let Some(eds) = cluster.eds_cluster_config.as_ref().filter(|_| is_eds) {
+            if is_xds(eds.eds_config.as_ref()) {
+                // The assignment is named after the cluster unless a service
+                // name is given.
+                let name = match eds.service_name.is_empty() {
+                    true => &cluster.name,
+                    false => &eds.service_name,
+                };
+
+                self.reference::<ClusterLoadAssignment>(name);
+            }
+        }
+
+        self.transport_socket(cluster.transport_socket.as_ref());
+
+        for socket in &cluster.transport_socket_matches {
+            self.transport_socket(socket.transport_socket.as_ref());
+        }
+    }
+
+    fn transport_socket(&mut self, socket: Option<&TransportSocket>) {
+        #[cfg(feature = "extensions-transport-sockets")]
+        if let Some(transport_socket::ConfigType::TypedConfig(config)) = socket.and_then(|x| x.config_type.as_ref()) {
+            if config.is::<DownstreamTlsContext>() {
+                if let Some(context) = self.decode::<DownstreamTlsContext>(config) {
+                    if let Some(common) = &context.common_tls_context {
+                        self.common_tls_context(common);
+                    }
+
+                    if let Some(SessionTicketKeysType::SessionTicketKeysSdsSecretConfig(sds)) = &context.session_ticket_keys_type {
+                        self.sds_secret_config(sds);
+                    }
+                }
+            } else if config.is::<UpstreamTlsContext>() {
+                if let Some(common) = self.decode::<UpstreamTlsContext>(config).and_then(|x| x.common_tls_context) {
+                    self.common_tls_context(&common);
+                }
+            }
+        }
+
+        let _ = socket;
+    }
+
+    #[cfg(feature = "extensions-transport-sockets")]
+    fn common_tls_context(&mut self, context: &CommonTlsContext) {
+        for sds in &context.tls_certificate_sds_secret_configs {
+            self.sds_secret_config(sds);
+        }
+
+        match &context.validation_context_type {
+            Some(ValidationContextType::ValidationContextSdsSecretConfig(sds)) => self.sds_secret_config(sds),
+            Some(ValidationContextType::CombinedValidationContext(combined)) => {
+                if let Some(sds) = &combined.validation_context_sds_secret_config {
+                    self.sds_secret_config(sds);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Secrets without a config source are static ones from the bootstrap.
+    #[cfg(feature = "extensions-transport-sockets")]
+    fn sds_secret_config(&mut self, sds: &SdsSecretConfig) {
+        if is_xds(sds.sds_config.as_ref()) {
+            self.reference::<Secret>(&sds.name);
+        }
+    }
+}
+
+impl Snapshot {
+    /// Checks that every resource the snapshot's listeners, clusters, route
+    /// configurations and extension configs reference over xDS is in the
+    /// snapshot, and that every route configuration, scoped route
+    /// configuration, cluster load assignment and secret is referenced.
+    ///
+    /// Clusters defined statically in the bootstrap are reported as dangling
+    /// when routes reference them, so callers relying on those may want to
+    /// filter the inconsistencies. Unused resources are only reported for
+    /// the types whose references can be followed with the enabled package
+    /// features.
+    pub fn check_consistency(&self) -> Result<(), ConsistencyError> {
+        let mut graph = Graph::default();
+
+        graph.collect(self, Graph::listener);
+        graph.collect(self, Graph::extension_config);
+        graph.collect(self, Graph::route_configuration);
+        graph.collect(self, Graph::scoped_route_configuration);
+        graph.collect(self, Graph::cluster);
+
+        let contains = |resource: &ResourceRef| {
+            self.get(&resource.type_url).is_some_and(|x| x.items.contains_key(&resource.name))
+        };
+
+        let mut inconsistencies: Vec<Inconsistency> = graph.references.iter()
+            .filter(|(_, to)| !contains(to))
+            .map(|(from, to)| Inconsistency::Dangling { from: from.clone(), to: to.clone() })
+            .collect();
+
+        let referenced: BTreeSet<&ResourceRef> = graph.references.iter().map(|(_, to)| to).collect();
+
+        let leaves = [
+            ClusterLoadAssignment::type_url(),
+            #[cfg(feature = "extensions-filters-network")]
+            RouteConfiguration::type_url(),
+            #[cfg(feature = "extensions-filters-network")]
+            ScopedRouteConfiguration::type_url(),
+            #[cfg(feature = "extensions-transport-sockets")]
+            Secret::type_url(),
+        ];
+
+        for type_url in leaves.into_iter().filter(|x| !graph.wildcards.contains(x)) {
+            let names = self.get(&type_url).into_iter().flat_map(|x| x.items.keys());
+
+            for name in names {
+                let resource = ResourceRef { type_url: type_url.clone(), name: name.clone() };
+
+                if !referenced.contains(&resource) {
+                    inconsistencies.push(Inconsistency::Unused(resource));
+                }
+            }
+        }
+
+        inconsistencies.append(&mut graph.errors);
+
+        match inconsistencies.is_empty() {
+            true => Ok(()),
+            false => Err(ConsistencyError { inconsistencies }),
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! The incremental variant of the protocol, where responses only carry the
+//! resources that changed and the names of those that were removed.
+
+use std::collections::{BTreeSet, HashMap};
+use tokio_stream::Stream;
+use tonic::Status;
+use crate::google::protobuf::Any;
+use crate::newest::envoy::service::discovery::v3::{DeltaDiscoveryRequest, DeltaDiscoveryResponse, Resource};
+use super::cache::{Resources, Snapshot};
+use super::server::Server;
+use super::stream::{self, Protocol, Responses, StreamState};
+
+#[derive(Default)]
+struct TypeState {
+    /// Whether the client subscribed to every resource of the type.
+    wildcard: bool,
+    /// The resources the client subscribed to by name.
+    names: BTreeSet<String>,
+    /// The version of every resource the client has, by name.
+    known: HashMap<String, String>,
+    /// The changes of the last response, applied to `known` once ACKed: the
+    /// version of each resource sent, or `None` for those removed.
+    unacked: HashMap<String, Option<String>>,
+    /// The names subscribed to since the last response, which must be
+    /// reported as removed if they do not exist.
+    pending: BTreeSet<String>,
+    /// The snapshot version of the last response.
+    sent: Option<String>,
+    nonce: Option<String>,
+    /// Whether the client has yet to ACK or NACK the last response. Only
+    /// one response per type is in flight at a time.
+    awaiting: bool,
+}
+
+impl TypeState {
+    fn contains(&self, name: &str) -> bool {
+        self.wildcard || self.names.contains(name)
+    }
+}
+
+/// Returns the version of a resource: the 64-bit FNV-1a hash of its encoding,
+/// so that it is the same on every stream and across restarts. Messages with
+/// map fields may encode differently once repacked, which only costs an
+/// unneeded update.
+fn get_resource_version(item: &Any) -> String {
+    let hash = item.value.iter().fold(0xcbf29ce484222325u64, |hash, x| (hash ^ u64::from(*x)).wrapping_mul(0x100000001b3));
+    format!("{hash:016x}")
+}
+
+struct DeltaStream {
+    state: StreamState,
+    types: HashMap<String, TypeState>,
+}
+
+impl DeltaStream {
+    /// Builds a response for `type_url` carrying the subscribed resources the
+    /// client does not have at their current version, and the ones it has
+    /// that are gone. The first response of a type is sent even if empty.
+    fn respond(&mut self, type_url: &str, snapshot: &Snapshot) -> Option<DeltaDiscoveryResponse> {
+        let types = self.types.get_mut(type_url)?;
+
+        if types.awaiting {
+            return None;
+        }
+
+        // A type that left the snapshot has no resources left, but the first
+        // response waits for it to be set.
+        let empty = Resources::default();
+        let resources = match snapshot.get(type_url) {
+            Some(resources) => resources,
+            None if types.sent.is_some() => &empty,
+            None => return None,
+        };
+
+        let updated: Vec<Resource> = resources.items.iter()
+            .filter(|(name, _)| types.contains(name))
+            .map(|(name, item)| (name, item, get_resource_version(item)))
+            .filter(|(name, _, version)| types.known.get(*name) != Some(version))
+            .map(|(name, item, version)| Resource {
+                name: name.clone(),
+                version,
+                resource: Some(item.clone()),
+                ..Default::default()
+            })
+            .collect();
+
+        let removed: BTreeSet<String> = types.known.keys()
+            .chain(&types.pending)
+            .filter(|x| !resources.items.contains_key(*x))
+            .cloned()
+            .collect();
+
+        types.pending.clear();
+
+        if updated.is_empty() && removed.is_empty() && types.sent.is_some() {
+            return None;
+        }
+
+        types.unacked = updated.iter()
+            .map(|x| (x.name.clone(), Some(x.version.clone())))
+            .chain(removed.iter().map(|x| (x.clone(), None)))
+            .collect();
+
+        let nonce = self.state.next_nonce();
+
+        types.sent = Some(resources.version.clone());
+        types.nonce = Some(nonce.clone());
+        types.awaiting = true;
+
+        Some(DeltaDiscoveryResponse {
+            system_version_info: resources.version.clone(),
+            resources: updated,
+            type_url: type_url.to_string(),
+            removed_resources: removed.into_iter().collect(),
+            nonce,
+            ..Default::default()
+        })
+    }
+}
+
+impl Protocol for DeltaStream {
+    type Request = DeltaDiscoveryRequest;
+    type Response = DeltaDiscoveryResponse;
+
+    fn state(&mut self) -> &mut StreamState {
+        &mut self.state
+    }
+
+    fn handle_request(&mut self, request: DeltaDiscoveryRequest) -> Result<Vec<DeltaDiscoveryResponse>, Status> {
+        self.state.identify(request.node.as_ref())?;
+
+        let type_url = self.state.get_type_url(&request.type_url)?;
+        let is_first = !self.types.contains_key(&type_url);
+        let types = self.types.entry(type_url.clone()).or_default();
+
+        if is_first {
+            // A first request without names is a wildcard subscription, and a
+            // reconnecting client lists the resources it already has.
+            types.wildcard = request.resource_names_subscribe.is_empty();
+            types.known = request.initial_resource_versions.clone().into_iter().collect();
+        }
+
+        if !request.response_nonce.is_empty() {
+            // Responses to anything but the latest response are stale.
+            if types.nonce.as_deref() != Some(request.response_nonce.as_str()) {
+                return Ok(vec![]);
+            }
+
+            types.awaiting = false;
+            let callbacks = &self.state.server.callbacks;
+            let version = types.sent.as_deref().unwrap_or("");
+            let unacked = std::mem::take(&mut types.unacked);
+
+            match &request.error_detail {
+                Some(error) => {
+                    callbacks.on_nack(self.state.node(), &type_url, version, error);
+
+                    // The client kept what it had, so the rejected resources
+                    // are sent again with the next snapshot rather than now.
+                    if request.resource_names_subscribe.is_empty() && request.resource_names_unsubscribe.is_empty() {
+                        return Ok(vec![]);
+                    }
+                },
+                None => {
+                    callbacks.on_ack(self.state.node(), &type_url, version);
+
+                    for (name, version) in unacked {
+                        match version {
+                            Some(version) => types.known.insert(name, version),
+                            None => types.known.remove(&name),
+                        };
+                    }
+                },
+            }
+        }
+
+        for name in &request.resource_names_subscribe {
+            match name.as_str() {
+                "*" => types.wildcard = true,
+                _ => {
+                    types.names.insert(name.clone());
+                    types.pending.insert(name.clone());
+                },
+            }
+        }
+
+        for name in &request.resource_names_unsubscribe {
+            match name.as_str() {
+                "*" => types.wildcard = false,
+                _ => {
+                    types.names.remove(name);
+                    types.pending.remove(name);
+                },
+            }
+        }
+
+        // The client drops the resources it is no longer subscribed to.
+        if !types.wildcard {
+            let names = &types.names;
+            types.known.retain(|name, _| names.contains(name));
+            types.unacked.retain(|name, _| names.contains(name));
+        }
+
+        let snapshot = self.state.snapshot();
+        Ok(snapshot.and_then(|x| self.respond(&type_url, &x)).into_iter().collect())
+    }
+
+    fn handle_snapshot(&mut self, snapshot: &Snapshot) -> Vec<DeltaDiscoveryResponse> {
+        let type_urls: Vec<String> = self.types.keys().cloned().collect();
+        type_urls.iter().filter_map(|x| self.respond(x, snapshot)).collect()
+    }
+}
+
+pub(crate) type DeltaResponses = Responses<DeltaDiscoveryResponse>;
+
+/// Serves an incremental stream, restricted to `type_url` when given.
+pub(crate) fn stream<S>(server: Server, requests: S, type_url: Option<&str>) -> DeltaResponses
+where
+    S: Stream<Item = Result<DeltaDiscoveryRequest, Status>> + Send + 'static,
+{
+    let stream = DeltaStream {
+        state: StreamState::new(server, type_url),
+        types: HashMap::new(),
+    };
+
+    stream::serve(stream, requests)
+}
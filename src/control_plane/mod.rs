@@ -0,0 +1,27 @@
+//! An xDS management server serving Envoy configuration from snapshots, in
+//! the style of go-control-plane.
+//!
+//! A [`SnapshotCache`] holds the latest [`Snapshot`] of each node. The
+//! [`Server`] implements the discovery services on top of it: it keeps track
+//! of what every stream subscribed to and was sent, detects ACKs and NACKs
+//! through the response nonces, and pushes new resources when a node's
+//! snapshot is replaced.
+//!
+//! Both variants of the protocol are served from the same snapshots. On
+//! incremental streams every resource is versioned by a hash of its
+//! encoding, so that only the resources that changed are sent again.
+
+mod cache;
+mod consistency;
+mod delta;
+mod server;
+// Their errors are the `Status` handed back to tonic as is.
+#[allow(clippy::result_large_err)]
+mod sotw;
+#[allow(clippy::result_large_err)]
+mod stream;
+
+pub use cache::{Resources, Snapshot, SnapshotCache};
+pub use consistency::{ConsistencyError, Inconsistency, ResourceRef};
+pub use server::{Callbacks, Server};
+pub use crate::resource::XdsResource;
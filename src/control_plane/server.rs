@@ -0,0 +1,213 @@
+use std::fmt;
+use std::sync::Arc;
+use tonic::{Request, Response, Status, Streaming};
+use crate::newest::google::rpc;
+use crate::resource::type_url;
+use crate::newest::envoy::config::core::v3::Node;
+use crate::newest::envoy::service::cluster::v3::cluster_discovery_service_server::ClusterDiscoveryService;
+use crate::newest::envoy::service::discovery::v3::aggregated_discovery_service_server::AggregatedDiscoveryService;
+use crate::newest::envoy::service::discovery::v3::{DeltaDiscoveryRequest, DiscoveryRequest, DiscoveryResponse};
+use crate::newest::envoy::service::endpoint::v3::endpoint_discovery_service_server::EndpointDiscoveryService;
+use crate::newest::envoy::service::extension::v3::extension_config_discovery_service_server::ExtensionConfigDiscoveryService;
+use crate::newest::envoy::service::listener::v3::listener_discovery_service_server::ListenerDiscoveryService;
+use crate::newest::envoy::service::route::v3::route_discovery_service_server::RouteDiscoveryService;
+use crate::newest::envoy::service::route::v3::scoped_routes_discovery_service_server::ScopedRoutesDiscoveryService;
+use crate::newest::envoy::service::route::v3::virtual_host_discovery_service_server::VirtualHostDiscoveryService;
+use crate::newest::envoy::service::runtime::v3::runtime_discovery_service_server::RuntimeDiscoveryService;
+use crate::newest::envoy::service::secret::v3::secret_discovery_service_server::SecretDiscoveryService;
+use super::cache::SnapshotCache;
+use super::delta::{self, DeltaResponses};
+use super::sotw::{self, SotwResponses};
+
+/// Hooks into the streams of a [`Server`], e.g. for logging or metrics. Every
+/// method does nothing by default.
+pub trait Callbacks: Send + Sync + 'static {
+    /// Called when a client accepts the resources of `type_url` at `version`,
+    /// which on incremental streams is the snapshot version of the response.
+    fn on_ack(&self, node: &Node, type_url: &str, version: &str) {
+        let _ = (node, type_url, version);
+    }
+
+    /// Called when a client rejects the resources of `type_url` at `version`,
+    /// with the error it reported. The client keeps its previous resources,
+    /// and is sent nothing more of that type until the snapshot changes. On
+    /// incremental streams, the rejected resources are then sent again.
+    fn on_nack(&self, node: &Node, type_url: &str, version: &str, error: &rpc::Status) {
+        let _ = (node, type_url, version, error);
+    }
+}
+
+impl Callbacks for () {}
+
+impl<T: Callbacks> Callbacks for Arc<T> {
+    fn on_ack(&self, node: &Node, type_url: &str, version: &str) {
+        T::on_ack(self, node, type_url, version)
+    }
+
+    fn on_nack(&self, node: &Node, type_url: &str, version: &str, error: &rpc::Status) {
+        T::on_nack(self, node, type_url, version, error)
+    }
+}
+
+/// An xDS management server serving the snapshots of a [`SnapshotCache`].
+///
+/// It implements the aggregated discovery service and the per-type ones, to
+/// be wrapped in their tonic servers, e.g.
+/// `AggregatedDiscoveryServiceServer::new(server.clone())`.
+#[derive(Clone)]
+pub struct Server {
+    pub(crate) cache: SnapshotCache,
+    pub(crate) callbacks: Arc<dyn Callbacks>,
+}
+
+impl fmt::Debug for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Server").field("cache", &self.cache).finish_non_exhaustive()
+    }
+}
+
+impl Server {
+    pub fn new(cache: SnapshotCache) -> Server {
+        Server {
+            cache,
+            callbacks: Arc::new(()),
+        }
+    }
+
+    /// Replaces the callbacks invoked by the server's streams.
+    pub fn with_callbacks(mut self, callbacks: impl Callbacks) -> Server {
+        self.callbacks = Arc::new(callbacks);
+        self
+    }
+
+    /// Returns the cache the server serves from.
+    pub fn cache(&self) -> &SnapshotCache {
+        &self.cache
+    }
+}
+
+#[tonic::async_trait]
+impl AggregatedDiscoveryService for Server {
+    type StreamAggregatedResourcesStream = SotwResponses;
+
+    async fn stream_aggregated_resources(
+        &self,
+        request: Request<Streaming<DiscoveryRequest>>,
+    ) -> Result<Response<Self::StreamAggregatedResourcesStream>, Status> {
+        Ok(Response::new(sotw::stream(self.clone(), request.into_inner(), None)))
+    }
+
+    type DeltaAggregatedResourcesStream = DeltaResponses;
+
+    async fn delta_aggregated_resources(
+        &self,
+        request: Request<Streaming<DeltaDiscoveryRequest>>,
+    ) -> Result<Response<Self::DeltaAggregatedResourcesStream>, Status> {
+        Ok(Response::new(delta::stream(self.clone(), request.into_inner(), None)))
+    }
+}
+
+/// Implements a per-type discovery service, which serves a single type and
+/// lets requests leave the type URL out.
+macro_rules! discovery_service {
+    (
+        $service:ident, $type_url:expr,
+        $stream:ident => $stream_type:ident,
+        $delta:ident => $delta_type:ident,
+        $fetch:ident,
+    ) => {
+        #[tonic::async_trait]
+        impl $service for Server {
+            type $stream_type = SotwResponses;
+
+            async fn $stream(
+                &self,
+                request: Request<Streaming<DiscoveryRequest>>,
+            ) -> Result<Response<Self::$stream_type>, Status> {
+                Ok(Response::new(sotw::stream(self.clone(), request.into_inner(), Some($type_url))))
+            }
+
+            type $delta_type = DeltaResponses;
+
+            async fn $delta(
+                &self,
+                request: Request<Streaming<DeltaDiscoveryRequest>>,
+            ) -> Result<Response<Self::$delta_type>, Status> {
+                Ok(Response::new(delta::stream(self.clone(), request.into_inner(), Some($type_url))))
+            }
+
+            async fn $fetch(&self, request: Request<DiscoveryRequest>) -> Result<Response<DiscoveryResponse>, Status> {
+                sotw::fetch(self, request.get_ref(), $type_url).map(Response::new)
+            }
+        }
+    };
+}
+
+discovery_service!(
+    ClusterDiscoveryService, type_url::CLUSTER,
+    stream_clusters => StreamClustersStream,
+    delta_clusters => DeltaClustersStream,
+    fetch_clusters,
+);
+
+discovery_service!(
+    EndpointDiscoveryService, type_url::CLUSTER_LOAD_ASSIGNMENT,
+    stream_endpoints => StreamEndpointsStream,
+    delta_endpoints => DeltaEndpointsStream,
+    fetch_endpoints,
+);
+
+discovery_service!(
+    ExtensionConfigDiscoveryService, type_url::TYPED_EXTENSION_CONFIG,
+    stream_extension_configs => StreamExtensionConfigsStream,
+    delta_extension_configs => DeltaExtensionConfigsStream,
+    fetch_extension_configs,
+);
+
+discovery_service!(
+    ListenerDiscoveryService, type_url::LISTENER,
+    stream_listeners => StreamListenersStream,
+    delta_listeners => DeltaListenersStream,
+    fetch_listeners,
+);
+
+discovery_service!(
+    RouteDiscoveryService, type_url::ROUTE_CONFIGURATION,
+    stream_routes => StreamRoutesStream,
+    delta_routes => DeltaRoutesStream,
+    fetch_routes,
+);
+
+discovery_service!(
+    RuntimeDiscoveryService, type_url::RUNTIME,
+    stream_runtime => StreamRuntimeStream,
+    delta_runtime => DeltaRuntimeStream,
+    fetch_runtime,
+);
+
+discovery_service!(
+    ScopedRoutesDiscoveryService, type_url::SCOPED_ROUTE_CONFIGURATION,
+    stream_scoped_routes => StreamScopedRoutesStream,
+    delta_scoped_routes => DeltaScopedRoutesStream,
+    fetch_scoped_routes,
+);
+
+discovery_service!(
+    SecretDiscoveryService, type_url::SECRET,
+    stream_secrets => StreamSecretsStream,
+    delta_secrets => DeltaSecretsStream,
+    fetch_secrets,
+);
+
+/// Virtual hosts are only served incrementally.
+#[tonic::async_trait]
+impl VirtualHostDiscoveryService for Server {
+    type DeltaVirtualHostsStream = DeltaResponses;
+
+    async fn delta_virtual_hosts(
+        &self,
+        request: Request<Streaming<DeltaDiscoveryRequest>>,
+    ) -> Result<Response<Self::DeltaVirtualHostsStream>, Status> {
+        Ok(Response::new(delta::stream(self.clone(), request.into_inner(), Some(type_url::VIRTUAL_HOST))))
+    }
+}
@@ -0,0 +1,173 @@
+//! The state-of-the-world variant of the protocol, where every response
+//! carries all the subscribed resources of its type.
+
+use std::collections::{BTreeSet, HashMap};
+use tokio_stream::Stream;
+use tonic::Status;
+use crate::google::protobuf::Any;
+use crate::newest::envoy::service::discovery::v3::{DiscoveryRequest, DiscoveryResponse};
+use super::cache::{Resources, Snapshot};
+use super::server::Server;
+use super::stream::{self, Protocol, Responses, StreamState};
+
+/// The resources of one type a client subscribed to.
+#[derive(Clone, Debug, PartialEq)]
+enum Subscription {
+    Wildcard,
+    Names(BTreeSet<String>),
+}
+
+impl Subscription {
+    /// Applies the `resource_names` of a request. An empty list only means
+    /// a wildcard subscription on the first request, and when acknowledging
+    /// one.
+    fn update(current: Option<&Subscription>, names: &[String]) -> Subscription {
+        match current {
+            _ if names.iter().any(|x| x == "*") => Subscription::Wildcard,
+            None | Some(Subscription::Wildcard) if names.is_empty() => Subscription::Wildcard,
+            _ => Subscription::Names(names.iter().cloned().collect()),
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        match self {
+            Subscription::Wildcard => true,
+            Subscription::Names(names) => names.contains(name),
+        }
+    }
+}
+
+#[derive(Default)]
+struct TypeState {
+    subscription: Option<Subscription>,
+    /// The version and subscription the last response was built from.
+    sent: Option<(String, Subscription)>,
+    nonce: Option<String>,
+    /// Whether the client has yet to ACK or NACK the last response. Only
+    /// one response per type is in flight at a time.
+    awaiting: bool,
+}
+
+fn get_items(resources: &Resources, subscription: &Subscription) -> Vec<Any> {
+    resources.items.iter()
+        .filter(|(name, _)| subscription.contains(name))
+        .map(|(_, item)| item.clone())
+        .collect()
+}
+
+/// Builds the response to a unary fetch, or fails if the client is already
+/// at the current version.
+pub(crate) fn fetch(server: &Server, request: &DiscoveryRequest, type_url: &str) -> Result<DiscoveryResponse, Status> {
+    let node = request.node.as_ref().ok_or_else(|| Status::invalid_argument("the request must identify the node"))?;
+    let key = server.cache.node_key(node);
+    let snapshot = server.cache.snapshot(&key)
+        .ok_or_else(|| Status::unavailable(format!("no snapshot for {key}")))?;
+    let resources = snapshot.get(type_url)
+        .ok_or_else(|| Status::unavailable(format!("no {type_url} resources for {key}")))?;
+
+    if request.version_info == resources.version {
+        return Err(Status::unavailable(format!("{type_url} is up to date at version {}", resources.version)));
+    }
+
+    Ok(DiscoveryResponse {
+        version_info: resources.version.clone(),
+        resources: get_items(resources, &Subscription::update(None, &request.resource_names)),
+        type_url: type_url.to_string(),
+        ..Default::default()
+    })
+}
+
+struct SotwStream {
+    state: StreamState,
+    types: HashMap<String, TypeState>,
+}
+
+impl SotwStream {
+    /// Builds a response for `type_url` if the client is subscribed to it and
+    /// has not been sent its resources at this version yet.
+    fn respond(&mut self, type_url: &str, snapshot: &Snapshot) -> Option<DiscoveryResponse> {
+        let types = self.types.get_mut(type_url)?;
+        let subscription = types.subscription.as_ref()?;
+        let resources = snapshot.get(type_url)?;
+
+        let is_sent = matches!(&types.sent, Some((version, sent)) if *version == resources.version && sent == subscription);
+
+        if types.awaiting || is_sent {
+            return None;
+        }
+
+        let nonce = self.state.next_nonce();
+        let items = get_items(resources, subscription);
+
+        types.sent = Some((resources.version.clone(), subscription.clone()));
+        types.nonce = Some(nonce.clone());
+        types.awaiting = true;
+
+        Some(DiscoveryResponse {
+            version_info: resources.version.clone(),
+            resources: items,
+            type_url: type_url.to_string(),
+            nonce,
+            ..Default::default()
+        })
+    }
+}
+
+impl Protocol for SotwStream {
+    type Request = DiscoveryRequest;
+    type Response = DiscoveryResponse;
+
+    fn state(&mut self) -> &mut StreamState {
+        &mut self.state
+    }
+
+    fn handle_request(&mut self, request: DiscoveryRequest) -> Result<Vec<DiscoveryResponse>, Status> {
+        self.state.identify(request.node.as_ref())?;
+
+        let type_url = self.state.get_type_url(&request.type_url)?;
+        let types = self.types.entry(type_url.clone()).or_default();
+
+        if !request.response_nonce.is_empty() {
+            // Responses to anything but the latest response are stale.
+            if types.nonce.as_deref() != Some(request.response_nonce.as_str()) {
+                return Ok(vec![]);
+            }
+
+            types.awaiting = false;
+            let callbacks = &self.state.server.callbacks;
+
+            match &request.error_detail {
+                Some(error) => {
+                    let rejected = types.sent.as_ref().map_or("", |(version, _)| version.as_str());
+                    callbacks.on_nack(self.state.node(), &type_url, rejected, error);
+                },
+                None => callbacks.on_ack(self.state.node(), &type_url, &request.version_info),
+            }
+        }
+
+        types.subscription = Some(Subscription::update(types.subscription.as_ref(), &request.resource_names));
+
+        let snapshot = self.state.snapshot();
+        Ok(snapshot.and_then(|x| self.respond(&type_url, &x)).into_iter().collect())
+    }
+
+    fn handle_snapshot(&mut self, snapshot: &Snapshot) -> Vec<DiscoveryResponse> {
+        let type_urls: Vec<String> = self.types.keys().cloned().collect();
+        type_urls.iter().filter_map(|x| self.respond(x, snapshot)).collect()
+    }
+}
+
+pub(crate) type SotwResponses = Responses<DiscoveryResponse>;
+
+/// Serves a state-of-the-world stream, restricted to `type_url` when given.
+pub(crate) fn stream<S>(server: Server, requests: S, type_url: Option<&str>) -> SotwResponses
+where
+    S: Stream<Item = Result<DiscoveryRequest, Status>> + Send + 'static,
+{
+    let stream = SotwStream {
+        state: StreamState::new(server, type_url),
+        types: HashMap::new(),
+    };
+
+    stream::serve(stream, requests)
+}
@@ -0,0 +1,155 @@
+//! What the state-of-the-world and incremental streams have in common: the
+//! node, the snapshots of that node, nonces and the event loop.
+
+use std::future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::Status;
+use crate::newest::envoy::config::core::v3::Node;
+use super::cache::Snapshot;
+use super::server::Server;
+
+pub(crate) type Responses<T> = ReceiverStream<Result<T, Status>>;
+type Snapshots = watch::Receiver<Option<Arc<Snapshot>>>;
+
+pub(crate) struct StreamState {
+    pub(crate) server: Server,
+    /// The only type served, for the per-type discovery services.
+    type_url: Option<String>,
+    node: Option<Node>,
+    snapshots: Option<Snapshots>,
+    nonce: u64,
+}
+
+impl StreamState {
+    pub(crate) fn new(server: Server, type_url: Option<&str>) -> StreamState {
+        StreamState {
+            server,
+            type_url: type_url.map(String::from),
+            node: None,
+            snapshots: None,
+            nonce: 0,
+        }
+    }
+
+    /// Starts watching the snapshots of the node on the first request, which
+    /// must identify it. Later requests may leave it out.
+    pub(crate) fn identify(&mut self, node: Option<&Node>) -> Result<(), Status> {
+        if self.node.is_some() {
+            return Ok(());
+        }
+
+        let node = node.ok_or_else(|| Status::invalid_argument("the first request must identify the node"))?;
+        self.snapshots = Some(self.server.cache.watch(&self.server.cache.node_key(node)));
+        self.node = Some(node.clone());
+
+        Ok(())
+    }
+
+    pub(crate) fn node(&self) -> &Node {
+        self.node.as_ref().expect("identified by the first request")
+    }
+
+    /// Returns the type URL of a request, which the per-type services allow
+    /// to be left out.
+    pub(crate) fn get_type_url(&self, type_url: &str) -> Result<String, Status> {
+        match (&self.type_url, type_url) {
+            (Some(expected), "") => Ok(expected.clone()),
+            (Some(expected), actual) if actual != expected => {
+                Err(Status::invalid_argument(format!("expected {expected} requests, got {actual}")))
+            },
+            (None, "") => Err(Status::invalid_argument("the request has no type URL")),
+            (_, actual) => Ok(actual.to_string()),
+        }
+    }
+
+    /// Returns the current snapshot of the node.
+    pub(crate) fn snapshot(&self) -> Option<Arc<Snapshot>> {
+        self.snapshots.as_ref().and_then(|x| x.borrow().clone())
+    }
+
+    pub(crate) fn next_nonce(&mut self) -> String {
+        self.nonce += 1;
+        self.nonce.to_string()
+    }
+}
+
+/// A variant of the protocol, driven by the requests of a stream and the
+/// snapshots of its node.
+pub(crate) trait Protocol: Send + 'static {
+    type Request: Send + 'static;
+    type Response: Send + 'static;
+
+    fn state(&mut self) -> &mut StreamState;
+
+    fn handle_request(&mut self, request: Self::Request) -> Result<Vec<Self::Response>, Status>;
+
+    fn handle_snapshot(&mut self, snapshot: &Snapshot) -> Vec<Self::Response>;
+}
+
+enum Event<T> {
+    Request(Option<Result<T, Status>>),
+    Snapshot(Option<Arc<Snapshot>>),
+}
+
+/// Waits for the next snapshot, or forever if the node is not known yet.
+async fn changed(snapshots: &mut Option<Snapshots>) -> Option<Arc<Snapshot>> {
+    let Some(snapshots) = snapshots else {
+        return future::pending().await;
+    };
+
+    match snapshots.changed().await {
+        Ok(()) => snapshots.borrow_and_update().clone(),
+        Err(_) => future::pending().await,
+    }
+}
+
+async fn run<P: Protocol>(
+    mut protocol: P,
+    mut requests: Pin<Box<dyn Stream<Item = Result<P::Request, Status>> + Send>>,
+    responses: mpsc::Sender<Result<P::Response, Status>>,
+) {
+    loop {
+        let event = tokio::select! {
+            request = requests.next() => Event::Request(request),
+            snapshot = changed(&mut protocol.state().snapshots) => Event::Snapshot(snapshot),
+            _ = responses.closed() => return,
+        };
+
+        let result = match event {
+            Event::Request(Some(Ok(request))) => protocol.handle_request(request),
+            // The client closed the stream, or it broke.
+            Event::Request(_) => return,
+            Event::Snapshot(Some(snapshot)) => Ok(protocol.handle_snapshot(&snapshot)),
+            Event::Snapshot(None) => continue,
+        };
+
+        let batch = match result {
+            Ok(batch) => batch,
+            Err(status) => {
+                let _ = responses.send(Err(status)).await;
+                return;
+            },
+        };
+
+        for response in batch {
+            if responses.send(Ok(response)).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Serves a stream on its own task.
+pub(crate) fn serve<P, S>(protocol: P, requests: S) -> Responses<P::Response>
+where
+    P: Protocol,
+    S: Stream<Item = Result<P::Request, Status>> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(16);
+    tokio::spawn(run(protocol, Box::pin(requests), sender));
+    ReceiverStream::new(receiver)
+}
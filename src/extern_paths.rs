@@ -0,0 +1,87 @@
+//! Support for downstream crates compiling their own protos that import the
+//! Envoy protos, reusing the envoypb types rather than generating them again.
+
+use std::collections::BTreeSet;
+use prost::Message;
+
+// Only the package names are needed, so the descriptor set is decoded into
+// this rather than pulling in prost-types.
+
+#[derive(Clone, PartialEq, Message)]
+struct PackageSet {
+    #[prost(message, repeated, tag = "1")]
+    file: Vec<PackageFile>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct PackageFile {
+    #[prost(string, optional, tag = "2")]
+    package: Option<String>,
+}
+
+/// Mirrors prost-build's identifier rules for the keywords that can appear
+/// in the package names.
+fn to_module_name(part: &str) -> String {
+    match part {
+        "as" | "break" | "const" | "continue" | "else" | "enum" | "false" | "fn" | "for" | "if"
+        | "impl" | "in" | "let" | "loop" | "match" | "mod" | "move" | "mut" | "pub" | "ref"
+        | "return" | "static" | "struct" | "trait" | "true" | "type" | "unsafe" | "use"
+        | "where" | "while" | "dyn" | "abstract" | "become" | "box" | "do" | "final" | "macro"
+        | "override" | "priv" | "typeof" | "unsized" | "virtual" | "yield" | "async" | "await"
+        | "try" => format!("r#{part}"),
+        "_" | "super" | "self" | "Self" | "extern" | "crate" => format!("{part}_"),
+        part => part.to_string(),
+    }
+}
+
+#[cfg(not(feature = "multi-version"))]
+fn get_module_root(_package: &str) -> String {
+    "::envoypb".to_string()
+}
+
+/// The well-known types live at the crate root, every other package in the
+/// module of the newest selected API version.
+#[cfg(feature = "multi-version")]
+fn get_module_root(package: &str) -> String {
+    match package {
+        "google.protobuf" => "::envoypb".to_string(),
+        _ => format!("::envoypb::{}", crate::NEWEST_VERSION_MODULE),
+    }
+}
+
+/// Returns the `extern_path` mapping of every package compiled into envoypb,
+/// as pairs of fully qualified proto package and Rust module path.
+///
+/// The packages of disabled package features are included too, code using
+/// them fails to build either way.
+pub fn extern_paths() -> Vec<(String, String)> {
+    let packages: BTreeSet<String> = PackageSet::decode(crate::FILE_DESCRIPTOR_SET)
+        .expect("embedded descriptor set is valid")
+        .file
+        .into_iter()
+        .filter_map(|x| x.package)
+        .collect();
+
+    packages.into_iter()
+        .map(|package| {
+            let module: Vec<String> = package.split('.').map(to_module_name).collect();
+            let path = format!("{}::{}", get_module_root(&package), module.join("::"));
+            (format!(".{package}"), path)
+        })
+        .collect()
+}
+
+/// Configures a prost-build `Config` to refer to the envoypb types for every
+/// package compiled into envoypb.
+///
+/// The Envoy protos themselves can be found through `DEP_ENVOYPB_INCLUDE`,
+/// which is only set when envoypb is built with `regenerate`. Builds from
+/// pre-generated sources have no protos to export.
+#[cfg(feature = "build-helpers")]
+pub fn configure_extern_paths(config: &mut prost_build::Config) -> &mut prost_build::Config {
+    for (proto_path, rust_path) in extern_paths() {
+        config.extern_path(proto_path, rust_path);
+    }
+
+    config
+}
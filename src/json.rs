@@ -0,0 +1,535 @@
+//! Proto3 JSON mapping for the well-known types.
+//!
+//! pbjson generates `Serialize` and `Deserialize` for every other message,
+//! but leaves the types with a special JSON representation to be implemented
+//! by hand. That of `Any` is in `any.rs`, as it is kept with `extern-wkt`.
+
+use std::collections::HashMap;
+use std::fmt;
+use base64::alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig, STANDARD};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use serde::de::{self, Deserializer, Error as _, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Error as _, SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use crate::google::protobuf::{
+    value, BoolValue, BytesValue, DoubleValue, Duration, Empty, FloatValue, Int32Value,
+    Int64Value, ListValue, NullValue, StringValue, Struct, Timestamp, UInt32Value, UInt64Value,
+    Value,
+};
+
+const MAX_DURATION_SECONDS: i64 = 315_576_000_000;
+const MIN_TIMESTAMP_SECONDS: i64 = -62_135_596_800;
+const MAX_TIMESTAMP_SECONDS: i64 = 253_402_300_799;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+fn format_nanos(nanos: u32) -> String {
+    if nanos == 0 {
+        String::new()
+    } else if nanos % 1_000_000 == 0 {
+        format!(".{:03}", nanos / 1_000_000)
+    } else if nanos % 1_000 == 0 {
+        format!(".{:06}", nanos / 1_000)
+    } else {
+        format!(".{nanos:09}")
+    }
+}
+
+fn parse_nanos(fraction: &str) -> Result<u32, String> {
+    if fraction.is_empty() || fraction.len() > 9 || !fraction.bytes().all(|x| x.is_ascii_digit()) {
+        return Err(format!("invalid fractional seconds {fraction:?}"));
+    }
+
+    Ok(fraction.parse::<u32>().unwrap() * 10u32.pow(9 - fraction.len() as u32))
+}
+
+fn parse_digits(value: &str) -> Result<i64, String> {
+    if value.is_empty() || !value.bytes().all(|x| x.is_ascii_digit()) {
+        return Err(format!("invalid number {value:?}"));
+    }
+
+    value.parse().map_err(|_| format!("number out of range {value:?}"))
+}
+
+impl Serialize for Duration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.seconds.abs() > MAX_DURATION_SECONDS
+            || self.nanos.abs() >= 1_000_000_000
+            || (self.seconds < 0 && self.nanos > 0)
+            || (self.seconds > 0 && self.nanos < 0)
+        {
+            return Err(S::Error::custom(format!("duration out of range: {self:?}")));
+        }
+
+        let sign = if self.seconds < 0 || self.nanos < 0 { "-" } else { "" };
+
+        serializer.serialize_str(&format!(
+            "{sign}{}{}s",
+            self.seconds.unsigned_abs(),
+            format_nanos(self.nanos.unsigned_abs()),
+        ))
+    }
+}
+
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let unsigned = value.strip_suffix('s').ok_or_else(|| format!("duration {value:?} must end in 's'"))?;
+    let (negative, unsigned) = match unsigned.strip_prefix('-') {
+        Some(x) => (true, x),
+        None => (false, unsigned),
+    };
+    let (seconds, nanos) = match unsigned.split_once('.') {
+        Some((seconds, fraction)) => (parse_digits(seconds)?, parse_nanos(fraction)? as i32),
+        None => (parse_digits(unsigned)?, 0),
+    };
+
+    if seconds > MAX_DURATION_SECONDS {
+        return Err(format!("duration out of range {value:?}"));
+    }
+
+    match negative {
+        true => Ok(Duration { seconds: -seconds, nanos: -nanos }),
+        false => Ok(Duration { seconds, nanos }),
+    }
+}
+
+impl<'de> Deserialize<'de> for Duration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        parse_duration(&String::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+// Conversions between days since the Unix epoch and proleptic Gregorian
+// dates, from http://howardhinnant.github.io/date_algorithms.html.
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        12 => days_from_civil(year + 1, 1, 1) - days_from_civil(year, 12, 1),
+        _ => days_from_civil(year, month + 1, 1) - days_from_civil(year, month, 1),
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !(MIN_TIMESTAMP_SECONDS..=MAX_TIMESTAMP_SECONDS).contains(&self.seconds)
+            || !(0..1_000_000_000).contains(&self.nanos)
+        {
+            return Err(S::Error::custom(format!("timestamp out of range: {self:?}")));
+        }
+
+        let (year, month, day) = civil_from_days(self.seconds.div_euclid(SECONDS_PER_DAY));
+        let time = self.seconds.rem_euclid(SECONDS_PER_DAY);
+
+        serializer.serialize_str(&format!(
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}{}Z",
+            time / 3_600,
+            time / 60 % 60,
+            time % 60,
+            format_nanos(self.nanos as u32),
+        ))
+    }
+}
+
+fn parse_timestamp(value: &str) -> Result<Timestamp, String> {
+    let invalid = || format!("invalid RFC 3339 timestamp {value:?}");
+    let bytes = value.as_bytes();
+
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+        || !bytes[..19].is_ascii()
+    {
+        return Err(invalid());
+    }
+
+    let field = |range: std::ops::Range<usize>| parse_digits(&value[range]).map_err(|_| invalid());
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(invalid());
+    }
+
+    let mut rest = &value[19..];
+    let mut nanos = 0;
+
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let end = fraction.find(|x: char| !x.is_ascii_digit()).unwrap_or(fraction.len());
+        nanos = parse_nanos(&fraction[..end]).map_err(|_| invalid())?;
+        rest = &fraction[end..];
+    }
+
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ if rest.len() == 6 && rest.is_ascii() && rest.as_bytes()[3] == b':' => {
+            let hours = parse_digits(&rest[1..3]).map_err(|_| invalid())?;
+            let minutes = parse_digits(&rest[4..6]).map_err(|_| invalid())?;
+            match &rest[..1] {
+                "+" => hours * 3_600 + minutes * 60,
+                "-" => -(hours * 3_600 + minutes * 60),
+                _ => return Err(invalid()),
+            }
+        },
+        _ => return Err(invalid()),
+    };
+
+    let seconds = days_from_civil(year, month, day) * SECONDS_PER_DAY
+        + hour * 3_600
+        + minute * 60
+        + second
+        - offset;
+
+    if !(MIN_TIMESTAMP_SECONDS..=MAX_TIMESTAMP_SECONDS).contains(&seconds) {
+        return Err(format!("timestamp out of range {value:?}"));
+    }
+
+    Ok(Timestamp { seconds, nanos: nanos as i32 })
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        parse_timestamp(&String::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for NullValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_unit()
+    }
+}
+
+impl<'de> Deserialize<'de> for NullValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            None => Ok(NullValue::NullValue),
+            Some(x) if x == "NULL_VALUE" => Ok(NullValue::NullValue),
+            Some(x) => Err(de::Error::invalid_value(de::Unexpected::Str(&x), &"null")),
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.kind {
+            None | Some(value::Kind::NullValue(_)) => serializer.serialize_unit(),
+            Some(value::Kind::NumberValue(x)) if !x.is_finite() => {
+                Err(S::Error::custom(format!("{x} cannot be represented as a Value")))
+            },
+            Some(value::Kind::NumberValue(x)) => serializer.serialize_f64(*x),
+            Some(value::Kind::StringValue(x)) => serializer.serialize_str(x),
+            Some(value::Kind::BoolValue(x)) => serializer.serialize_bool(*x),
+            Some(value::Kind::StructValue(x)) => x.serialize(serializer),
+            Some(value::Kind::ListValue(x)) => x.serialize(serializer),
+        }
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON value")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Value, E> {
+        Ok(Value { kind: Some(value::Kind::BoolValue(value)) })
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Value, E> {
+        Ok(Value { kind: Some(value::Kind::NumberValue(value as f64)) })
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Value, E> {
+        Ok(Value { kind: Some(value::Kind::NumberValue(value as f64)) })
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Value, E> {
+        Ok(Value { kind: Some(value::Kind::NumberValue(value)) })
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Value, E> {
+        Ok(Value { kind: Some(value::Kind::StringValue(value.to_string())) })
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Value, E> {
+        Ok(Value { kind: Some(value::Kind::StringValue(value)) })
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value { kind: Some(value::Kind::NullValue(NullValue::NullValue as i32)) })
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        self.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut values = vec![];
+
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+
+        Ok(Value { kind: Some(value::Kind::ListValue(ListValue { values })) })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut fields = Struct::default();
+
+        while let Some((key, value)) = map.next_entry()? {
+            fields.fields.insert(key, value);
+        }
+
+        Ok(Value { kind: Some(value::Kind::StructValue(fields)) })
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+impl Serialize for Struct {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.fields.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Struct {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Struct { fields: Deserialize::deserialize(deserializer)? })
+    }
+}
+
+impl Serialize for ListValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.values.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ListValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(ListValue { values: Vec::deserialize(deserializer)? })
+    }
+}
+
+impl Serialize for Empty {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_map(Some(0))?.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Empty {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fields = HashMap::<String, IgnoredAny>::deserialize(deserializer)?;
+
+        match fields.keys().next() {
+            Some(key) => Err(de::Error::unknown_field(key, &[])),
+            None => Ok(Empty {}),
+        }
+    }
+}
+
+/// Implements the JSON mapping of a wrapper type, given how its `value` is
+/// serialized and the JSON type it is deserialized from.
+macro_rules! impl_wrapper {
+    ($name:ident, |$s:ident: &$value:ty| $serialize:expr, |$d:ident: $json:ty| $deserialize:expr) => {
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let $s: &$value = &self.value;
+                $serialize.serialize(serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let $d = <$json>::deserialize(deserializer)?;
+                Ok($name { value: $deserialize })
+            }
+        }
+    };
+}
+
+/// A float serialized with the proto3 JSON spellings of the special values.
+struct Float(f64, bool);
+
+impl Serialize for Float {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.0.is_nan() {
+            serializer.serialize_str("NaN")
+        } else if self.0.is_infinite() {
+            serializer.serialize_str(if self.0 > 0.0 { "Infinity" } else { "-Infinity" })
+        } else if self.1 {
+            serializer.serialize_f32(self.0 as f32)
+        } else {
+            serializer.serialize_f64(self.0)
+        }
+    }
+}
+
+/// A number as written in JSON, where proto3 allows a string as well.
+enum JsonNumber {
+    Signed(i64),
+    Unsigned(u64),
+    Float(f64),
+    String(String),
+}
+
+struct JsonNumberVisitor;
+
+impl Visitor<'_> for JsonNumberVisitor {
+    type Value = JsonNumber;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a number or a string holding one")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<JsonNumber, E> {
+        Ok(JsonNumber::Signed(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<JsonNumber, E> {
+        Ok(JsonNumber::Unsigned(value))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<JsonNumber, E> {
+        Ok(JsonNumber::Float(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<JsonNumber, E> {
+        Ok(JsonNumber::String(value.to_string()))
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonNumber {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(JsonNumberVisitor)
+    }
+}
+
+/// Converts a float to an integer type if it has no fractional part and is
+/// in range, as `1e3` is a valid spelling of an integer in proto3 JSON.
+fn get_integral<T: TryFrom<i128>>(value: f64) -> Option<T> {
+    match value.is_finite() && value.fract() == 0.0 {
+        true => T::try_from(value as i128).ok(),
+        false => None,
+    }
+}
+
+/// A number of type `T` read from either of its proto3 JSON spellings.
+struct Number<T>(T);
+
+macro_rules! impl_integer {
+    ($($ty:ty),*) => {$(
+        impl<'de> Deserialize<'de> for Number<$ty> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let value = match JsonNumber::deserialize(deserializer)? {
+                    JsonNumber::Signed(x) => <$ty>::try_from(x).ok(),
+                    JsonNumber::Unsigned(x) => <$ty>::try_from(x).ok(),
+                    JsonNumber::Float(x) => get_integral(x),
+                    JsonNumber::String(x) => x.parse().ok().or_else(|| get_integral(x.parse().ok()?)),
+                };
+
+                value.map(Number).ok_or_else(|| D::Error::custom(concat!("invalid ", stringify!($ty))))
+            }
+        }
+    )*};
+}
+
+impl_integer!(i32, u32, i64, u64);
+
+macro_rules! impl_float {
+    ($($ty:ty),*) => {$(
+        impl<'de> Deserialize<'de> for Number<$ty> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let value = match JsonNumber::deserialize(deserializer)? {
+                    JsonNumber::Signed(x) => Some(x as $ty),
+                    JsonNumber::Unsigned(x) => Some(x as $ty),
+                    // Doubles too large for a float would become infinite.
+                    JsonNumber::Float(x) => Some(x as $ty).filter(|x| x.is_finite()),
+                    JsonNumber::String(x) => match x.as_str() {
+                        "NaN" => Some(<$ty>::NAN),
+                        "Infinity" => Some(<$ty>::INFINITY),
+                        "-Infinity" => Some(<$ty>::NEG_INFINITY),
+                        _ => x.parse::<$ty>().ok().filter(|x| x.is_finite()),
+                    },
+                };
+
+                value.map(Number).ok_or_else(|| D::Error::custom(concat!("invalid ", stringify!($ty))))
+            }
+        }
+    )*};
+}
+
+impl_float!(f32, f64);
+
+/// Decodes base64 with either alphabet, padded or not, as proto3 JSON allows.
+fn decode_base64(value: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    const ENGINE: GeneralPurpose = GeneralPurpose::new(
+        &alphabet::STANDARD,
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+    );
+
+    ENGINE.decode(value.replace('-', "+").replace('_', "/"))
+}
+
+/// Bytes read from their base64 JSON spelling.
+struct Base64(Vec<u8>);
+
+impl<'de> Deserialize<'de> for Base64 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        decode_base64(&String::deserialize(deserializer)?)
+            .map(Base64)
+            .map_err(D::Error::custom)
+    }
+}
+
+impl_wrapper!(BoolValue, |x: &bool| x, |x: bool| x);
+impl_wrapper!(StringValue, |x: &String| x, |x: String| x);
+impl_wrapper!(BytesValue, |x: &Vec<u8>| STANDARD.encode(x), |x: Base64| x.0);
+impl_wrapper!(Int32Value, |x: &i32| x, |x: Number<i32>| x.0);
+impl_wrapper!(UInt32Value, |x: &u32| x, |x: Number<u32>| x.0);
+impl_wrapper!(Int64Value, |x: &i64| x.to_string(), |x: Number<i64>| x.0);
+impl_wrapper!(UInt64Value, |x: &u64| x.to_string(), |x: Number<u64>| x.0);
+impl_wrapper!(FloatValue, |x: &f32| Float(*x as f64, true), |x: Number<f32>| x.0);
+impl_wrapper!(DoubleValue, |x: &f64| Float(*x, false), |x: Number<f64>| x.0);
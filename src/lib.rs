@@ -1,2 +1,72 @@
-#![allow(clippy::all)]
-tonic::include_proto!("mod");
+#[cfg(feature = "multi-version")]
+#[macro_use]
+mod versions;
+
+#[cfg(not(feature = "multi-version"))]
+include!(concat!(env!("ENVOYPB_GENERATED_DIR"), "/mod.rs"));
+#[cfg(not(feature = "multi-version"))]
+include!(concat!(env!("ENVOYPB_GENERATED_DIR"), "/registry.rs"));
+
+#[cfg(feature = "multi-version")]
+include!(concat!(env!("OUT_DIR"), "/versions.rs"));
+
+// The generated code used by the control plane, which is that of the newest
+// selected version with `multi-version`.
+#[cfg(not(feature = "multi-version"))]
+#[allow(unused_imports)]
+pub(crate) use crate as newest;
+
+/// The well-known types, shared by every API version.
+#[cfg(feature = "multi-version")]
+#[allow(clippy::all, deprecated)]
+pub mod google {
+    pub mod protobuf {
+        #[cfg(not(feature = "extern-wkt"))]
+        include!(concat!(env!("ENVOYPB_GENERATED_DIR"), "/google.protobuf.rs"));
+        #[cfg(all(feature = "validate", not(feature = "extern-wkt")))]
+        include!(concat!(env!("ENVOYPB_GENERATED_DIR"), "/google.protobuf.validate.rs"));
+        #[cfg(all(feature = "extern-wkt", feature = "serde"))]
+        pub use ::pbjson_types::*;
+        #[cfg(all(feature = "extern-wkt", not(feature = "serde")))]
+        pub use ::prost_types::*;
+        #[cfg(feature = "extern-wkt")]
+        pub use crate::any::Any;
+    }
+}
+
+/// The serialized `FileDescriptorSet` of every proto compiled for the selected
+/// API version, including source info. It covers all packages regardless of
+/// the enabled package features. With `multi-version`, this is the descriptor
+/// set of the newest selected version.
+pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!(concat!(env!("ENVOYPB_GENERATED_DIR"), "/file_descriptor_set.bin"));
+
+mod any;
+#[cfg(feature = "control-plane")]
+pub mod control_plane;
+mod extern_paths;
+// pbjson-types implements the JSON mapping of its well-known types itself.
+#[cfg(all(feature = "serde", not(feature = "extern-wkt")))]
+mod json;
+#[cfg(feature = "reflect")]
+mod reflect;
+mod registry;
+pub mod resource;
+// Not `validate`, which is the package of protoc-gen-validate's own protos.
+#[cfg(feature = "validate")]
+mod validation;
+#[cfg(feature = "xds-client")]
+pub mod xds_client;
+
+pub use any::AnyError;
+#[cfg(feature = "extern-wkt")]
+pub use any::AnyExt;
+#[cfg(feature = "build-helpers")]
+pub use extern_paths::configure_extern_paths;
+pub use extern_paths::extern_paths;
+#[cfg(feature = "reflect")]
+pub use reflect::descriptor_pool;
+pub use registry::{DynMessage, TypeRegistry};
+#[cfg(feature = "multi-version")]
+pub use versions::convert;
+#[cfg(feature = "validate")]
+pub use validation::{Validate, ValidationError, Violation};
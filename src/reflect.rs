@@ -0,0 +1,15 @@
+//! Runtime reflection over the compiled Envoy protos.
+
+use std::sync::OnceLock;
+use prost_reflect::DescriptorPool;
+
+/// Returns a descriptor pool built from [`FILE_DESCRIPTOR_SET`](crate::FILE_DESCRIPTOR_SET),
+/// decoded on first use.
+pub fn descriptor_pool() -> &'static DescriptorPool {
+    static POOL: OnceLock<DescriptorPool> = OnceLock::new();
+
+    POOL.get_or_init(|| {
+        DescriptorPool::decode(crate::FILE_DESCRIPTOR_SET)
+            .expect("embedded file descriptor set is valid")
+    })
+}
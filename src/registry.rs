@@ -0,0 +1,152 @@
+//! Registry of the message types compiled into the crate, keyed by their
+//! fully qualified protobuf name.
+
+use std::any::Any as StdAny;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+use prost::{Message, Name};
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::google::protobuf::Any;
+use crate::AnyError;
+
+/// A decoded message of a type only known at runtime.
+pub trait DynMessage: StdAny + fmt::Debug + Send + Sync {
+    /// Returns the type URL of the message.
+    fn type_url(&self) -> String;
+
+    /// Encodes the message to a newly allocated buffer.
+    fn encode_to_vec(&self) -> Vec<u8>;
+
+    fn as_any(&self) -> &dyn StdAny;
+}
+
+impl<T: Message + Name + 'static> DynMessage for T {
+    fn type_url(&self) -> String {
+        T::type_url()
+    }
+
+    fn encode_to_vec(&self) -> Vec<u8> {
+        Message::encode_to_vec(self)
+    }
+
+    fn as_any(&self) -> &dyn StdAny {
+        self
+    }
+}
+
+impl dyn DynMessage {
+    /// Returns the message as a `T` if that is its concrete type.
+    pub fn downcast_ref<T: DynMessage>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+}
+
+type Decode = fn(&[u8]) -> Result<Box<dyn DynMessage>, prost::DecodeError>;
+#[cfg(feature = "serde")]
+type ToJson = fn(&[u8]) -> Result<serde_json::Value, String>;
+#[cfg(feature = "serde")]
+type FromJson = fn(serde_json::Value) -> Result<Vec<u8>, String>;
+
+pub(crate) struct TypeEntry {
+    pub(crate) decode: Decode,
+    #[cfg(feature = "serde")]
+    pub(crate) to_json: ToJson,
+    #[cfg(feature = "serde")]
+    pub(crate) from_json: FromJson,
+}
+
+/// Maps the type URL of every message compiled into the crate, under the
+/// enabled package features, to its decoder.
+pub struct TypeRegistry {
+    entries: HashMap<&'static str, TypeEntry>,
+}
+
+/// Returns the fully qualified message name of a type URL, which is the part
+/// after the last `/`.
+pub(crate) fn get_type_name(type_url: &str) -> &str {
+    type_url.rsplit_once('/').map_or(type_url, |(_, name)| name)
+}
+
+fn decode<T: Message + Name + Default + 'static>(value: &[u8]) -> Result<Box<dyn DynMessage>, prost::DecodeError> {
+    Ok(Box::new(T::decode(value)?))
+}
+
+#[cfg(feature = "serde")]
+fn to_json<T: Message + Default + Serialize>(value: &[u8]) -> Result<serde_json::Value, String> {
+    let message = T::decode(value).map_err(|x| x.to_string())?;
+    serde_json::to_value(message).map_err(|x| x.to_string())
+}
+
+#[cfg(feature = "serde")]
+fn from_json<T: Message + DeserializeOwned>(value: serde_json::Value) -> Result<Vec<u8>, String> {
+    let message: T = serde_json::from_value(value).map_err(|x| x.to_string())?;
+    Ok(message.encode_to_vec())
+}
+
+impl TypeRegistry {
+    #[cfg(not(feature = "serde"))]
+    pub(crate) fn register<T>(&mut self, name: &'static str)
+    where
+        T: Message + Name + Default + 'static,
+    {
+        self.entries.insert(name, TypeEntry {
+            decode: decode::<T>,
+        });
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn register<T>(&mut self, name: &'static str)
+    where
+        T: Message + Name + Default + Serialize + DeserializeOwned + 'static,
+    {
+        self.entries.insert(name, TypeEntry {
+            decode: decode::<T>,
+            to_json: to_json::<T>,
+            from_json: from_json::<T>,
+        });
+    }
+
+    pub(crate) fn new(register_types: fn(&mut TypeRegistry)) -> TypeRegistry {
+        let mut registry = TypeRegistry { entries: HashMap::new() };
+        register_types(&mut registry);
+        registry
+    }
+
+    /// Returns the registry of the messages compiled into the crate. With
+    /// `multi-version`, this is the registry of the newest selected API
+    /// version, and each version module has its own `type_registry()`.
+    pub fn global() -> &'static TypeRegistry {
+        static REGISTRY: OnceLock<TypeRegistry> = OnceLock::new();
+
+        REGISTRY.get_or_init(|| TypeRegistry::new(crate::register_types))
+    }
+
+    pub(crate) fn get(&self, type_url: &str) -> Option<&TypeEntry> {
+        self.entries.get(get_type_name(type_url))
+    }
+
+    /// Returns whether a message type is registered for `type_url`.
+    pub fn contains(&self, type_url: &str) -> bool {
+        self.get(type_url).is_some()
+    }
+
+    /// Lists the fully qualified names of the registered message types.
+    pub fn type_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.entries.keys().copied()
+    }
+
+    /// Decodes the message packed in `any` into its concrete type.
+    pub fn decode(&self, any: &Any) -> Result<Box<dyn DynMessage>, AnyError> {
+        let entry = self.get(&any.type_url)
+            .ok_or_else(|| AnyError::UnknownType(any.type_url.clone()))?;
+
+        (entry.decode)(&any.value).map_err(|source| AnyError::Decode {
+            type_url: any.type_url.clone(),
+            source,
+        })
+    }
+}
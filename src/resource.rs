@@ -0,0 +1,145 @@
+//! The xDS resource types: their type URLs, and a [`Resource`] enum over the
+//! messages served with them, built against the newest selected API version.
+
+use prost::{Message, Name};
+use crate::google::protobuf::Any;
+use crate::newest::envoy::service::discovery::v3 as discovery;
+use crate::AnyError;
+
+/// A message served over xDS, subscribed to by the name in one of its fields.
+pub trait XdsResource: Message + Name {
+    /// Returns the name of the resource.
+    ///
+    /// For a `VirtualHost`, this is its `name` field. VHDS names virtual hosts
+    /// `<route_configuration_name>/<domain>` instead, which callers must build
+    /// themselves, as `Snapshot::with_virtual_hosts` of the control plane does.
+    fn resource_name(&self) -> &str;
+}
+
+/// Declares the type URL constants, the variants of [`Resource`] and their
+/// `XdsResource` implementations from one table. Constants are declared for
+/// every type, variants only for the types whose package is compiled.
+macro_rules! resources {
+    ($($(#[$attr:meta])* $variant:ident($path:path) => $field:ident, $constant:ident = $type_url:literal;)*) => {
+        /// The type URLs of the xDS resource types.
+        pub mod type_url {
+            $(
+                #[doc = concat!("The type URL of `", stringify!($variant), "` resources.")]
+                pub const $constant: &str = $type_url;
+            )*
+        }
+
+        $(
+            $(#[$attr])*
+            impl XdsResource for $path {
+                fn resource_name(&self) -> &str {
+                    &self.$field
+                }
+            }
+
+            $(#[$attr])*
+            impl From<$path> for Resource {
+                fn from(resource: $path) -> Resource {
+                    Resource::$variant(resource)
+                }
+            }
+        )*
+
+        /// A resource of any of the xDS resource types.
+        // Holds the messages unboxed, like the oneofs prost generates.
+        #[allow(clippy::large_enum_variant)]
+        #[derive(Clone, Debug, PartialEq)]
+        pub enum Resource {
+            $($(#[$attr])* $variant($path),)*
+        }
+
+        impl Resource {
+            /// Returns the type URL of the resource.
+            pub fn type_url(&self) -> &'static str {
+                match self {
+                    $($(#[$attr])* Resource::$variant(_) => type_url::$constant,)*
+                }
+            }
+
+            /// Returns the name of the resource, which for a `VirtualHost` is
+            /// not its VHDS name. See [`XdsResource::resource_name`].
+            pub fn name(&self) -> &str {
+                match self {
+                    $($(#[$attr])* Resource::$variant(x) => x.resource_name(),)*
+                }
+            }
+
+            /// Packs the resource into an `Any`.
+            pub fn to_any(&self) -> Any {
+                match self {
+                    $($(#[$attr])* Resource::$variant(x) => Any::pack(x),)*
+                }
+            }
+
+            /// Unpacks a resource of any of the types, going by its type URL.
+            pub fn from_any(any: &Any) -> Result<Resource, AnyError> {
+                $(
+                    $(#[$attr])*
+                    if any.is::<$path>() {
+                        return any.unpack().map(Resource::$variant);
+                    }
+                )*
+
+                Err(AnyError::UnknownType(any.type_url.clone()))
+            }
+        }
+    };
+}
+
+resources! {
+    Cluster(crate::newest::envoy::config::cluster::v3::Cluster) => name,
+        CLUSTER = "type.googleapis.com/envoy.config.cluster.v3.Cluster";
+    ClusterLoadAssignment(crate::newest::envoy::config::endpoint::v3::ClusterLoadAssignment) => cluster_name,
+        CLUSTER_LOAD_ASSIGNMENT = "type.googleapis.com/envoy.config.endpoint.v3.ClusterLoadAssignment";
+    Listener(crate::newest::envoy::config::listener::v3::Listener) => name,
+        LISTENER = "type.googleapis.com/envoy.config.listener.v3.Listener";
+    RouteConfiguration(crate::newest::envoy::config::route::v3::RouteConfiguration) => name,
+        ROUTE_CONFIGURATION = "type.googleapis.com/envoy.config.route.v3.RouteConfiguration";
+    ScopedRouteConfiguration(crate::newest::envoy::config::route::v3::ScopedRouteConfiguration) => name,
+        SCOPED_ROUTE_CONFIGURATION = "type.googleapis.com/envoy.config.route.v3.ScopedRouteConfiguration";
+    VirtualHost(crate::newest::envoy::config::route::v3::VirtualHost) => name,
+        VIRTUAL_HOST = "type.googleapis.com/envoy.config.route.v3.VirtualHost";
+    #[cfg(feature = "extensions-transport-sockets")]
+    Secret(crate::newest::envoy::extensions::transport_sockets::tls::v3::Secret) => name,
+        SECRET = "type.googleapis.com/envoy.extensions.transport_sockets.tls.v3.Secret";
+    Runtime(crate::newest::envoy::service::runtime::v3::Runtime) => name,
+        RUNTIME = "type.googleapis.com/envoy.service.runtime.v3.Runtime";
+    TypedExtensionConfig(crate::newest::envoy::config::core::v3::TypedExtensionConfig) => name,
+        TYPED_EXTENSION_CONFIG = "type.googleapis.com/envoy.config.core.v3.TypedExtensionConfig";
+}
+
+impl Resource {
+    /// Wraps the resource in the `Resource` message of incremental responses,
+    /// at `version`. It is named after [`Resource::name`], so virtual hosts
+    /// served over VHDS must have the wrapper renamed.
+    pub fn to_resource(&self, version: impl Into<String>) -> discovery::Resource {
+        discovery::Resource {
+            name: self.name().to_string(),
+            version: version.into(),
+            resource: Some(self.to_any()),
+            ..Default::default()
+        }
+    }
+
+    /// Unpacks the resources of a state-of-the-world response. Resources
+    /// wrapped in a `Resource` message, as they are to carry a TTL, are
+    /// unwrapped, and those wrapping nothing are TTL refreshes and skipped.
+    pub fn from_response(response: &discovery::DiscoveryResponse) -> Result<Vec<Resource>, AnyError> {
+        let mut resources = vec![];
+
+        for any in &response.resources {
+            if !any.is::<discovery::Resource>() {
+                resources.push(Resource::from_any(any)?);
+            } else if let Some(any) = any.unpack::<discovery::Resource>()?.resource {
+                resources.push(Resource::from_any(&any)?);
+            }
+        }
+
+        Ok(resources)
+    }
+}
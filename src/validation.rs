@@ -0,0 +1,216 @@
+//! Validation of the `(validate.rules)` constraints declared on the Envoy
+//! protos with protoc-gen-validate.
+//!
+//! The `Validate` implementations are generated by the build script. They
+//! check every field recursively and collect all violations, each with the
+//! path of the offending field, rather than stopping at the first one.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use regex::Regex;
+
+/// A single constraint violation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// Path of the offending field, e.g. `static_resources.clusters[0].name`.
+    pub field: String,
+    pub message: String,
+}
+
+impl Violation {
+    pub(crate) fn new(field: &str, message: impl Into<String>) -> Violation {
+        Violation {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.field.is_empty() {
+            true => f.write_str(&self.message),
+            false => write!(f, "{}: {}", self.field, self.message),
+        }
+    }
+}
+
+/// Error returned when a message violates its constraints.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationError {
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} constraint violation(s)", self.violations.len())?;
+
+        for violation in &self.violations {
+            write!(f, "\n  {violation}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for ValidationError {}
+
+/// Checks a message against its protoc-gen-validate constraints.
+pub trait Validate {
+    /// Validates the message and every message nested in it, returning all
+    /// violations found.
+    fn validate(&self) -> Result<(), ValidationError> {
+        let mut violations = vec![];
+        self.validate_into("", &mut violations);
+
+        match violations.is_empty() {
+            true => Ok(()),
+            false => Err(ValidationError { violations }),
+        }
+    }
+
+    /// Appends the violations of the message, located at `path`, to
+    /// `violations`.
+    fn validate_into(&self, path: &str, violations: &mut Vec<Violation>);
+}
+
+impl<T: Validate> Validate for Box<T> {
+    fn validate_into(&self, path: &str, violations: &mut Vec<Violation>) {
+        (**self).validate_into(path, violations)
+    }
+}
+
+// Helpers called by the generated implementations. Which of them are used
+// depends on the enabled package features.
+
+pub(crate) fn join(path: &str, field: &str) -> String {
+    match path.is_empty() {
+        true => field.to_string(),
+        false => format!("{path}.{field}"),
+    }
+}
+
+pub(crate) fn is_match(pattern: &'static str, value: &str) -> bool {
+    static CACHE: OnceLock<Mutex<HashMap<&'static str, Regex>>> = OnceLock::new();
+
+    let mut cache = CACHE.get_or_init(Default::default).lock().unwrap();
+    let regex = cache.entry(pattern)
+        .or_insert_with(|| Regex::new(pattern).expect("validation pattern is a valid regex"));
+
+    regex.is_match(value)
+}
+
+pub(crate) fn contains_bytes(value: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty() || value.windows(needle.len()).any(|x| x == needle)
+}
+
+pub(crate) fn has_duplicates<T: PartialEq>(items: &[T]) -> bool {
+    items.iter().enumerate().any(|(index, item)| items[..index].contains(item))
+}
+
+fn to_nanos(seconds: i64, nanos: i32) -> i128 {
+    seconds as i128 * 1_000_000_000 + nanos as i128
+}
+
+/// Returns the current time as `(seconds, nanos)`, comparable with a
+/// timestamp's fields.
+pub(crate) fn now() -> (i64, i32) {
+    let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    (elapsed.as_secs() as i64, elapsed.subsec_nanos() as i32)
+}
+
+pub(crate) fn is_within(seconds: i64, nanos: i32, within: (i64, i32)) -> bool {
+    let (now_seconds, now_nanos) = now();
+    let distance = (to_nanos(seconds, nanos) - to_nanos(now_seconds, now_nanos)).abs();
+    distance <= to_nanos(within.0, within.1)
+}
+
+pub(crate) fn is_ip(value: &str) -> bool {
+    value.parse::<IpAddr>().is_ok()
+}
+
+pub(crate) fn is_ipv4(value: &str) -> bool {
+    value.parse::<Ipv4Addr>().is_ok()
+}
+
+pub(crate) fn is_ipv6(value: &str) -> bool {
+    value.parse::<Ipv6Addr>().is_ok()
+}
+
+pub(crate) fn is_hostname(value: &str) -> bool {
+    let value = value.strip_suffix('.').unwrap_or(value);
+
+    if value.is_empty() || value.len() > 253 {
+        return false;
+    }
+
+    value.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.bytes().all(|x| x.is_ascii_alphanumeric() || x == b'-')
+    })
+}
+
+pub(crate) fn is_address(value: &str) -> bool {
+    is_ip(value) || is_hostname(value)
+}
+
+pub(crate) fn is_email(value: &str) -> bool {
+    let value = match (value.find('<'), value.ends_with('>')) {
+        (Some(start), true) => &value[start + 1..value.len() - 1],
+        _ => value,
+    };
+
+    match value.rsplit_once('@') {
+        Some((local, domain)) => !local.is_empty() && local.len() <= 64 && is_hostname(domain),
+        None => false,
+    }
+}
+
+pub(crate) fn is_uri(value: &str) -> bool {
+    match value.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.starts_with(|x: char| x.is_ascii_alphabetic())
+                && scheme.bytes().all(|x| x.is_ascii_alphanumeric() || b"+-.".contains(&x))
+        },
+        None => false,
+    }
+}
+
+pub(crate) fn is_uri_ref(value: &str) -> bool {
+    !value.chars().any(|x| x.is_whitespace() || x.is_control())
+}
+
+pub(crate) fn is_uuid(value: &str) -> bool {
+    let groups: Vec<&str> = value.split('-').collect();
+
+    groups.len() == 5
+        && groups.iter().zip([8, 4, 4, 4, 12]).all(|(group, len)| {
+            group.len() == len && group.bytes().all(|x| x.is_ascii_hexdigit())
+        })
+}
+
+pub(crate) fn is_http_header_name(value: &str, strict: bool) -> bool {
+    match strict {
+        true => {
+            let name = value.strip_prefix(':').unwrap_or(value);
+            !name.is_empty() && name.bytes().all(|x| x.is_ascii_alphanumeric() || b"!#$%&'*+-.^_|~`".contains(&x))
+        },
+        false => !value.contains(['\0', '\n', '\r']),
+    }
+}
+
+pub(crate) fn is_http_header_value(value: &str, strict: bool) -> bool {
+    match strict {
+        true => !value.chars().any(|x| (x < ' ' && x != '\t') || x == '\x7f'),
+        false => !value.contains(['\0', '\n', '\r']),
+    }
+}
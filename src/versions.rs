@@ -0,0 +1,47 @@
+//! Several Envoy API versions compiled side by side, each in its own module
+//! named after the version, e.g. `v1_30`.
+//!
+//! The well-known types are compiled once at the crate root and shared by
+//! every version, so an `Any` or a `Duration` can be passed between them.
+
+use prost::{Message, Name};
+use crate::google::protobuf::Any;
+use crate::AnyError;
+
+/// Declares the module of an API version, given the directory of its
+/// generated sources.
+macro_rules! api_version {
+    ($(#[$attr:meta])* $name:ident, $dir:literal) => {
+        $(#[$attr])*
+        pub mod $name {
+            include!(concat!($dir, "/mod.rs"));
+            include!(concat!($dir, "/registry.rs"));
+
+            /// The serialized `FileDescriptorSet` of every proto compiled for
+            /// this API version, including source info.
+            pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!(concat!($dir, "/file_descriptor_set.bin"));
+
+            /// Returns the registry of the messages compiled for this API
+            /// version.
+            pub fn type_registry() -> &'static crate::TypeRegistry {
+                static REGISTRY: std::sync::OnceLock<crate::TypeRegistry> = std::sync::OnceLock::new();
+
+                REGISTRY.get_or_init(|| crate::TypeRegistry::new(register_types))
+            }
+        }
+    };
+}
+
+/// Converts a message to the message of the same name in another API version
+/// by re-encoding it.
+///
+/// Both types must have the same fully qualified name. Fields the target
+/// version does not know are dropped, and fields it added are left at their
+/// defaults.
+pub fn convert<T, U>(message: &T) -> Result<U, AnyError>
+where
+    T: Message + Name,
+    U: Message + Name + Default,
+{
+    Any::pack(message).unpack()
+}
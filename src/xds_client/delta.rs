@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use tonic::transport::Channel;
+use crate::newest::envoy::config::core::v3::Node;
+use crate::newest::envoy::service::discovery::v3::aggregated_discovery_service_client::AggregatedDiscoveryServiceClient;
+use crate::newest::envoy::service::discovery::v3::{DeltaDiscoveryRequest, DeltaDiscoveryResponse};
+use super::session::{get_error_detail, Opening, Requests, Session};
+use super::{Interest, Shared};
+
+pub(crate) struct DeltaSession {
+    /// The node, until the first request of the stream carries it.
+    node: Option<Node>,
+    /// The subscription last sent for each type.
+    sent: HashMap<String, Interest>,
+}
+
+impl Session for DeltaSession {
+    type Request = DeltaDiscoveryRequest;
+    type Response = DeltaDiscoveryResponse;
+
+    fn new(shared: &Shared) -> DeltaSession {
+        DeltaSession {
+            node: Some(shared.node.clone()),
+            sent: HashMap::new(),
+        }
+    }
+
+    fn open(mut client: AggregatedDiscoveryServiceClient<Channel>, requests: Requests<DeltaDiscoveryRequest>) -> Opening<DeltaDiscoveryResponse> {
+        Box::pin(async move { client.delta_aggregated_resources(requests).await.map(|x| x.into_inner()) })
+    }
+
+    /// Sends the difference between the watched and the subscribed names. A
+    /// reconnecting client lists the resources it has, so that only those
+    /// that changed meanwhile are sent again.
+    fn subscribe(&mut self, shared: &Shared) -> Vec<DeltaDiscoveryRequest> {
+        let mut requests = vec![];
+
+        for (type_url, interest, received) in shared.interests() {
+            let sent = self.sent.get(&type_url);
+
+            if sent == Some(&interest) || (sent.is_none() && interest == Interest::default()) {
+                continue;
+            }
+
+            let is_first = sent.is_none();
+            let sent = sent.cloned().unwrap_or_default();
+            let mut subscribe: Vec<String> = interest.names.difference(&sent.names).cloned().collect();
+            let mut unsubscribe: Vec<String> = sent.names.difference(&interest.names).cloned().collect();
+
+            match (interest.wildcard, sent.wildcard) {
+                (true, false) => subscribe.push("*".to_string()),
+                (false, true) => unsubscribe.push("*".to_string()),
+                _ => {},
+            }
+
+            // The server forgets the resources no longer subscribed to.
+            if !interest.wildcard {
+                shared.retain(&type_url, &interest.names);
+            }
+
+            requests.push(DeltaDiscoveryRequest {
+                node: self.node.take(),
+                type_url: type_url.clone(),
+                resource_names_subscribe: subscribe,
+                resource_names_unsubscribe: unsubscribe,
+                initial_resource_versions: match is_first {
+                    true => received.versions,
+                    false => HashMap::new(),
+                },
+                ..Default::default()
+            });
+
+            self.sent.insert(type_url, interest);
+        }
+
+        requests
+    }
+
+    fn handle_response(&mut self, shared: &Shared, response: DeltaDiscoveryResponse) -> Option<DeltaDiscoveryRequest> {
+        // Resources wrapping nothing only refresh their TTL.
+        let resources: Vec<_> = response.resources.iter()
+            .filter_map(|x| Some((x, x.resource.as_ref()?)))
+            .collect();
+
+        let mut request = DeltaDiscoveryRequest {
+            type_url: response.type_url.clone(),
+            response_nonce: response.nonce.clone(),
+            ..Default::default()
+        };
+
+        match shared.decode(&response.type_url, resources.iter().map(|(_, item)| *item)) {
+            Ok(items) => shared.accept(&response.type_url, |x| {
+                x.version = Some(response.system_version_info.clone());
+
+                for ((name, item), (resource, _)) in items.into_iter().zip(&resources) {
+                    // The wrapper carries the name subscribed to, which for
+                    // VHDS isn't that of the message.
+                    let name = match resource.name.is_empty() {
+                        true => name,
+                        false => resource.name.clone(),
+                    };
+                    x.versions.insert(name.clone(), resource.version.clone());
+                    x.items.insert(name, item);
+                }
+
+                for name in &response.removed_resources {
+                    x.items.remove(name);
+                    x.versions.remove(name);
+                }
+            }),
+            Err(message) => request.error_detail = Some(get_error_detail(message)),
+        }
+
+        Some(request)
+    }
+}
@@ -0,0 +1,333 @@
+//! An xDS client subscribing to a management server over the aggregated
+//! discovery service, for tools that consume Envoy configuration without
+//! being Envoy.
+//!
+//! An [`XdsClient`] keeps one stream open to the server, identifying itself
+//! with its node, and reconnects with exponential backoff when the stream
+//! breaks. Resources are subscribed to with typed [`Watch`]es, which the
+//! client aggregates into one subscription per type. Every response is
+//! decoded before it is accepted: responses that fail to decode are NACKed
+//! with the error in `error_detail`, and the watches keep the resources they
+//! had.
+
+mod delta;
+mod session;
+mod sotw;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tonic::transport::Channel;
+use crate::google::protobuf::Any;
+use crate::newest::envoy::config::core::v3::Node;
+use crate::resource::XdsResource;
+use crate::AnyError;
+
+/// The variant of the xDS protocol a client speaks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// Every response carries all the subscribed resources of its type.
+    #[default]
+    StateOfTheWorld,
+    /// Responses only carry the resources that changed and the names of
+    /// those that were removed.
+    Incremental,
+}
+
+/// How an [`XdsClient`] talks to its server.
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    pub protocol: Protocol,
+    /// The delay before the first reconnection attempt. It doubles with every
+    /// failed attempt, and is reset once a stream gets a response.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ClientOptions {
+    fn default() -> ClientOptions {
+        ClientOptions {
+            protocol: Protocol::StateOfTheWorld,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The accepted resources of one type, encoded.
+#[derive(Clone, Debug, Default)]
+struct Received {
+    /// The version of the last accepted response, if any.
+    version: Option<String>,
+    items: BTreeMap<String, Any>,
+    /// The version of every resource, with the incremental protocol.
+    versions: HashMap<String, String>,
+}
+
+/// The resources of one type a client subscribes to.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Interest {
+    wildcard: bool,
+    names: BTreeSet<String>,
+}
+
+struct TypeState {
+    /// The number of watches of every resource of the type.
+    wildcard: usize,
+    /// The number of watches of each resource name.
+    names: BTreeMap<String, usize>,
+    /// Decodes a resource, returning its name.
+    decode: fn(&Any) -> Result<String, AnyError>,
+    received: watch::Sender<Received>,
+}
+
+impl TypeState {
+    fn interest(&self) -> Interest {
+        Interest {
+            wildcard: self.wildcard > 0,
+            names: self.names.keys().cloned().collect(),
+        }
+    }
+}
+
+fn decode<M: XdsResource + Default>(any: &Any) -> Result<String, AnyError> {
+    any.unpack::<M>().map(|x| x.resource_name().to_string())
+}
+
+/// The state shared by the handles of a client and its task.
+struct Shared {
+    node: Node,
+    types: Mutex<HashMap<String, TypeState>>,
+}
+
+impl Shared {
+    /// Lists the subscription of every watched type.
+    fn interests(&self) -> Vec<(String, Interest, Received)> {
+        let types = self.types.lock().unwrap();
+        types.iter()
+            .map(|(type_url, state)| (type_url.clone(), state.interest(), state.received.borrow().clone()))
+            .collect()
+    }
+
+    /// Returns the received resources of `type_url`.
+    fn received(&self, type_url: &str) -> Received {
+        let types = self.types.lock().unwrap();
+        types.get(type_url).map(|x| x.received.borrow().clone()).unwrap_or_default()
+    }
+
+    /// Decodes `items`, or returns the error to NACK them with. Resources of
+    /// types nobody watches are accepted unseen.
+    fn decode<'a>(&self, type_url: &str, items: impl IntoIterator<Item = &'a Any>) -> Result<Vec<(String, Any)>, String> {
+        let types = self.types.lock().unwrap();
+        let Some(state) = types.get(type_url) else {
+            return Ok(vec![]);
+        };
+
+        items.into_iter()
+            .enumerate()
+            .map(|(i, item)| match (state.decode)(item) {
+                Ok(name) => Ok((name, item.clone())),
+                Err(error) => Err(format!("resource {i}: {error}")),
+            })
+            .collect()
+    }
+
+    /// Applies an accepted response to the resources of `type_url`, and
+    /// notifies its watches.
+    fn accept(&self, type_url: &str, apply: impl FnOnce(&mut Received)) {
+        let types = self.types.lock().unwrap();
+
+        if let Some(state) = types.get(type_url) {
+            state.received.send_modify(apply);
+        }
+    }
+
+    /// Forgets the resources of `type_url` not named in `names`, notifying
+    /// the watches only if there were any.
+    fn retain(&self, type_url: &str, names: &BTreeSet<String>) {
+        let types = self.types.lock().unwrap();
+
+        if let Some(state) = types.get(type_url) {
+            state.received.send_if_modified(|x| {
+                let count = x.items.len();
+                x.items.retain(|name, _| names.contains(name));
+                x.versions.retain(|name, _| names.contains(name));
+                x.items.len() != count
+            });
+        }
+    }
+}
+
+/// A client of an xDS management server.
+///
+/// The client runs on a tokio task, spawned by [`XdsClient::new`], until it
+/// and all its watches are dropped. Cloning it is cheap.
+#[derive(Clone)]
+pub struct XdsClient {
+    shared: Arc<Shared>,
+    /// Wakes the task when subscriptions change.
+    changes: mpsc::UnboundedSender<()>,
+}
+
+impl XdsClient {
+    /// Starts a client of the server at the other end of `channel`, which
+    /// identifies itself as `node`. Must be called within a tokio runtime.
+    pub fn new(channel: Channel, node: Node, options: ClientOptions) -> XdsClient {
+        let shared = Arc::new(Shared {
+            node,
+            types: Mutex::new(HashMap::new()),
+        });
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(session::run(shared.clone(), channel, options, receiver));
+
+        XdsClient {
+            shared,
+            changes: sender,
+        }
+    }
+
+    /// Returns the node the client identifies itself as.
+    pub fn node(&self) -> &Node {
+        &self.shared.node
+    }
+
+    /// Watches every resource of type `M`.
+    pub fn watch_all<M: XdsResource + Default>(&self) -> Watch<M> {
+        self.subscribe(None)
+    }
+
+    /// Watches the resources of type `M` named `names`.
+    pub fn watch<M: XdsResource + Default>(&self, names: impl IntoIterator<Item = impl Into<String>>) -> Watch<M> {
+        self.subscribe(Some(names.into_iter().map(Into::into).collect()))
+    }
+
+    fn subscribe<M: XdsResource + Default>(&self, names: Option<BTreeSet<String>>) -> Watch<M> {
+        let type_url = M::type_url();
+        let mut types = self.shared.types.lock().unwrap();
+        let state = types.entry(type_url.clone()).or_insert_with(|| TypeState {
+            wildcard: 0,
+            names: BTreeMap::new(),
+            decode: decode::<M>,
+            received: watch::channel(Received::default()).0,
+        });
+
+        let interest = state.interest();
+
+        match &names {
+            None => state.wildcard += 1,
+            Some(names) => {
+                for name in names {
+                    *state.names.entry(name.clone()).or_default() += 1;
+                }
+            },
+        }
+
+        // Resources accepted before the watch was made are its first update,
+        // unless it subscribes to more and the server has yet to respond.
+        let mut receiver = state.received.subscribe();
+
+        if interest.wildcard || state.interest() == interest {
+            receiver.mark_changed();
+        }
+        drop(types);
+        let _ = self.changes.send(());
+
+        Watch {
+            type_url,
+            names,
+            receiver,
+            delivered: None,
+            client: self.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+/// The resources of a [`Watch`] at a version of the server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Update<M> {
+    /// The version of the response the resources were accepted from.
+    pub version: String,
+    pub resources: BTreeMap<String, M>,
+}
+
+/// A subscription to resources of type `M`, which delivers their updates.
+///
+/// Dropping the watch unsubscribes from the resources no other watch of the
+/// client is interested in.
+pub struct Watch<M> {
+    type_url: String,
+    /// The watched names, or `None` for every resource of the type.
+    names: Option<BTreeSet<String>>,
+    receiver: watch::Receiver<Received>,
+    /// The resources of the last update, to skip updates of other names.
+    delivered: Option<BTreeMap<String, Any>>,
+    client: XdsClient,
+    marker: PhantomData<fn() -> M>,
+}
+
+impl<M: XdsResource + Default> Watch<M> {
+    fn get_items(&self, received: &Received) -> BTreeMap<String, Any> {
+        received.items.iter()
+            .filter(|(name, _)| self.names.as_ref().is_none_or(|x| x.contains(*name)))
+            .map(|(name, item)| (name.clone(), item.clone()))
+            .collect()
+    }
+
+    /// Returns the current resources, or `None` if none were accepted yet.
+    pub fn current(&self) -> Option<Update<M>> {
+        let received = self.receiver.borrow();
+        let version = received.version.clone()?;
+        let resources = self.get_items(&received).iter()
+            .filter_map(|(name, item)| Some((name.clone(), item.unpack().ok()?)))
+            .collect();
+
+        Some(Update { version, resources })
+    }
+
+    /// Waits for the watched resources to change, which includes the first
+    /// response, and returns them.
+    pub async fn changed(&mut self) -> Update<M> {
+        loop {
+            // The sender lives as long as the client shared with the watch.
+            self.receiver.changed().await.expect("the client outlives its watches");
+
+            let received = self.receiver.borrow_and_update().clone();
+            let items = self.get_items(&received);
+
+            if received.version.is_some() && self.delivered.as_ref() != Some(&items) {
+                self.delivered = Some(items);
+                return self.current().expect("accepted");
+            }
+        }
+    }
+}
+
+impl<M> Drop for Watch<M> {
+    fn drop(&mut self) {
+        let mut types = self.client.shared.types.lock().unwrap();
+
+        if let Some(state) = types.get_mut(&self.type_url) {
+            match &self.names {
+                None => state.wildcard -= 1,
+                Some(names) => {
+                    for name in names {
+                        if let Some(count) = state.names.get_mut(name) {
+                            *count -= 1;
+
+                            if *count == 0 {
+                                state.names.remove(name);
+                            }
+                        }
+                    }
+                },
+            }
+        }
+
+        drop(types);
+        let _ = self.client.changes.send(());
+    }
+}
@@ -0,0 +1,131 @@
+//! What the state-of-the-world and incremental sessions have in common: the
+//! stream to the server, and reconnecting when it breaks.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tonic::transport::Channel;
+use tonic::{Status, Streaming};
+use crate::newest::envoy::service::discovery::v3::aggregated_discovery_service_client::AggregatedDiscoveryServiceClient;
+use crate::newest::google::rpc;
+use super::{delta, sotw, ClientOptions, Protocol, Shared};
+
+pub(crate) type Requests<T> = UnboundedReceiverStream<T>;
+pub(crate) type Opening<T> = Pin<Box<dyn Future<Output = Result<Streaming<T>, Status>> + Send>>;
+
+/// One stream to the server, in a variant of the protocol.
+pub(crate) trait Session: Send + Sized + 'static {
+    type Request: Send + 'static;
+    type Response: Send + 'static;
+
+    fn new(shared: &Shared) -> Self;
+
+    fn open(client: AggregatedDiscoveryServiceClient<Channel>, requests: Requests<Self::Request>) -> Opening<Self::Response>;
+
+    /// Returns the requests bringing the subscriptions of the stream up to
+    /// date with the watches.
+    fn subscribe(&mut self, shared: &Shared) -> Vec<Self::Request>;
+
+    /// Accepts or rejects a response, returning the ACK or NACK.
+    fn handle_response(&mut self, shared: &Shared, response: Self::Response) -> Option<Self::Request>;
+}
+
+/// Returns the `error_detail` of a NACK.
+pub(crate) fn get_error_detail(message: String) -> rpc::Status {
+    rpc::Status {
+        code: tonic::Code::InvalidArgument as i32,
+        message,
+        details: vec![],
+    }
+}
+
+enum Outcome {
+    /// The stream broke, or could not be opened.
+    Disconnected,
+    /// Every handle of the client was dropped.
+    Closed,
+}
+
+/// Serves one stream until it breaks. The backoff is reset once the server
+/// responds.
+async fn serve<S: Session>(
+    shared: &Shared,
+    channel: &Channel,
+    options: &ClientOptions,
+    changes: &mut mpsc::UnboundedReceiver<()>,
+    backoff: &mut std::time::Duration,
+) -> Outcome {
+    let mut session = S::new(shared);
+    let (requests, receiver) = mpsc::unbounded_channel();
+
+    for request in session.subscribe(shared) {
+        let _ = requests.send(request);
+    }
+
+    let client = AggregatedDiscoveryServiceClient::new(channel.clone());
+    let mut responses = match S::open(client, UnboundedReceiverStream::new(receiver)).await {
+        Ok(responses) => responses,
+        Err(_) => return Outcome::Disconnected,
+    };
+
+    loop {
+        tokio::select! {
+            response = responses.message() => match response {
+                Ok(Some(response)) => {
+                    *backoff = options.initial_backoff;
+
+                    if let Some(request) = session.handle_response(shared, response) {
+                        let _ = requests.send(request);
+                    }
+                },
+                _ => return Outcome::Disconnected,
+            },
+            change = changes.recv() => match change {
+                Some(()) => {
+                    for request in session.subscribe(shared) {
+                        let _ = requests.send(request);
+                    }
+                },
+                None => return Outcome::Closed,
+            },
+        }
+    }
+}
+
+/// Keeps a stream open to the server until the client is dropped.
+pub(crate) async fn run(
+    shared: Arc<Shared>,
+    channel: Channel,
+    options: ClientOptions,
+    mut changes: mpsc::UnboundedReceiver<()>,
+) {
+    let mut backoff = options.initial_backoff;
+
+    loop {
+        let outcome = match options.protocol {
+            Protocol::StateOfTheWorld => serve::<sotw::SotwSession>(&shared, &channel, &options, &mut changes, &mut backoff).await,
+            Protocol::Incremental => serve::<delta::DeltaSession>(&shared, &channel, &options, &mut changes, &mut backoff).await,
+        };
+
+        if let Outcome::Closed = outcome {
+            return;
+        }
+
+        let sleep = tokio::time::sleep(backoff);
+        tokio::pin!(sleep);
+
+        // Subscriptions made meanwhile are sent once reconnected.
+        loop {
+            tokio::select! {
+                _ = &mut sleep => break,
+                change = changes.recv() => if change.is_none() {
+                    return;
+                },
+            }
+        }
+
+        backoff = (backoff * 2).min(options.max_backoff);
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use tonic::transport::Channel;
+use crate::google::protobuf::Any;
+use crate::newest::envoy::config::core::v3::Node;
+use crate::newest::envoy::service::discovery::v3::aggregated_discovery_service_client::AggregatedDiscoveryServiceClient;
+use crate::newest::envoy::service::discovery::v3::{DiscoveryRequest, DiscoveryResponse, Resource};
+use super::session::{get_error_detail, Opening, Requests, Session};
+use super::{Interest, Received, Shared};
+
+/// Returns the `resource_names` of a subscription. An empty list subscribes
+/// to everything on the first request of a type and keeps doing so after,
+/// so `*` is only needed to turn an explicit subscription into a wildcard.
+fn get_resource_names(interest: &Interest, sent: Option<&Interest>) -> Vec<String> {
+    match interest.wildcard {
+        true if sent.is_none_or(|x| x.wildcard) => vec![],
+        true => vec!["*".to_string()],
+        false => interest.names.iter().cloned().collect(),
+    }
+}
+
+/// Unwraps the resources sent in a `Resource` message to carry a TTL. Those
+/// wrapping nothing only refresh the TTL of a resource the client has.
+fn unwrap_resources(resources: Vec<Any>, received: &Received) -> Result<Vec<Any>, String> {
+    let mut unwrapped = vec![];
+
+    for (i, item) in resources.into_iter().enumerate() {
+        if !item.is::<Resource>() {
+            unwrapped.push(item);
+            continue;
+        }
+
+        let wrapper = item.unpack::<Resource>().map_err(|x| format!("resource {i}: {x}"))?;
+
+        match wrapper.resource {
+            Some(item) => unwrapped.push(item),
+            None => unwrapped.extend(received.items.get(&wrapper.name).cloned()),
+        }
+    }
+
+    Ok(unwrapped)
+}
+
+pub(crate) struct SotwSession {
+    /// The node, until the first request of the stream carries it.
+    node: Option<Node>,
+    /// The subscription last sent for each type.
+    sent: HashMap<String, Interest>,
+    /// The nonce of the last response of each type.
+    nonces: HashMap<String, String>,
+}
+
+impl SotwSession {
+    fn request(&mut self, type_url: &str, version: Option<String>) -> DiscoveryRequest {
+        let interest = self.sent.get(type_url).cloned().unwrap_or_default();
+
+        DiscoveryRequest {
+            version_info: version.unwrap_or_default(),
+            node: self.node.take(),
+            resource_names: get_resource_names(&interest, Some(&interest)),
+            type_url: type_url.to_string(),
+            response_nonce: self.nonces.get(type_url).cloned().unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Session for SotwSession {
+    type Request = DiscoveryRequest;
+    type Response = DiscoveryResponse;
+
+    fn new(shared: &Shared) -> SotwSession {
+        SotwSession {
+            node: Some(shared.node.clone()),
+            sent: HashMap::new(),
+            nonces: HashMap::new(),
+        }
+    }
+
+    fn open(mut client: AggregatedDiscoveryServiceClient<Channel>, requests: Requests<DiscoveryRequest>) -> Opening<DiscoveryResponse> {
+        Box::pin(async move { client.stream_aggregated_resources(requests).await.map(|x| x.into_inner()) })
+    }
+
+    fn subscribe(&mut self, shared: &Shared) -> Vec<DiscoveryRequest> {
+        let mut requests = vec![];
+
+        for (type_url, interest, received) in shared.interests() {
+            let sent = self.sent.get(&type_url);
+
+            // A first request without names would subscribe to everything.
+            if sent == Some(&interest) || (sent.is_none() && interest == Interest::default()) {
+                continue;
+            }
+
+            let resource_names = get_resource_names(&interest, sent);
+            self.sent.insert(type_url.clone(), interest);
+
+            let mut request = self.request(&type_url, received.version);
+            request.resource_names = resource_names;
+            requests.push(request);
+        }
+
+        requests
+    }
+
+    fn handle_response(&mut self, shared: &Shared, response: DiscoveryResponse) -> Option<DiscoveryRequest> {
+        let DiscoveryResponse { version_info, resources, type_url, nonce, .. } = response;
+        self.nonces.insert(type_url.clone(), nonce);
+
+        let received = shared.received(&type_url);
+        let items = unwrap_resources(resources, &received)
+            .and_then(|x| shared.decode(&type_url, &x));
+
+        match items {
+            Ok(items) => {
+                shared.accept(&type_url, |x| {
+                    x.version = Some(version_info.clone());
+                    x.items = items.into_iter().collect();
+                });
+
+                Some(self.request(&type_url, Some(version_info)))
+            },
+            Err(message) => {
+                let mut request = self.request(&type_url, received.version);
+                request.error_detail = Some(get_error_detail(message));
+                Some(request)
+            },
+        }
+    }
+}
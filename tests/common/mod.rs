@@ -0,0 +1,182 @@
+//! An in-process management server, and raw streams to it.
+
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use envoypb::control_plane::{Callbacks, Server};
+use envoypb::envoy::config::cluster::v3::Cluster;
+use envoypb::envoy::config::core::v3::Node;
+use envoypb::envoy::service::discovery::v3::aggregated_discovery_service_client::AggregatedDiscoveryServiceClient;
+use envoypb::envoy::service::discovery::v3::aggregated_discovery_service_server::AggregatedDiscoveryServiceServer;
+use envoypb::envoy::service::discovery::v3::{DeltaDiscoveryRequest, DeltaDiscoveryResponse, DiscoveryRequest, DiscoveryResponse};
+use envoypb::google::rpc;
+use tokio::net::TcpSocket;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{TcpListenerStream, UnboundedReceiverStream};
+use tonic::transport::{Channel, Endpoint};
+use tonic::Streaming;
+
+/// How long to wait for a response that must come.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for a response that must not come.
+pub const QUIET: Duration = Duration::from_millis(300);
+
+pub fn node(id: &str) -> Node {
+    Node {
+        id: id.to_string(),
+        ..Default::default()
+    }
+}
+
+pub fn cluster(name: &str, stat_name: &str) -> Cluster {
+    Cluster {
+        name: name.to_string(),
+        alt_stat_name: stat_name.to_string(),
+        ..Default::default()
+    }
+}
+
+/// A server running on a runtime of its own, so that stopping it closes its
+/// connections as a restart would.
+pub struct TestServer {
+    pub addr: SocketAddr,
+    runtime: Option<Runtime>,
+}
+
+impl TestServer {
+    /// Serves the aggregated discovery service on a free local port.
+    pub fn start(server: Server) -> TestServer {
+        TestServer::start_at(server, "127.0.0.1:0".parse().unwrap())
+    }
+
+    pub fn start_at(server: Server, addr: SocketAddr) -> TestServer {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        runtime.spawn(async move {
+            // Rebinds the port of a stopped server right away.
+            let socket = TcpSocket::new_v4().unwrap();
+            socket.set_reuseaddr(true).unwrap();
+            socket.bind(addr).unwrap();
+            let listener = socket.listen(16).unwrap();
+            sender.send(listener.local_addr().unwrap()).unwrap();
+
+            tonic::transport::Server::builder()
+                .add_service(AggregatedDiscoveryServiceServer::new(server))
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await
+                .unwrap();
+        });
+
+        TestServer {
+            addr: receiver.recv().unwrap(),
+            runtime: Some(runtime),
+        }
+    }
+
+    pub fn channel(&self) -> Channel {
+        Endpoint::from_shared(format!("http://{}", self.addr)).unwrap().connect_lazy()
+    }
+
+    /// Stops the server, dropping every stream it serves.
+    pub fn stop(mut self) -> SocketAddr {
+        self.runtime.take().unwrap().shutdown_background();
+        self.addr
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// A stream opened by hand, to drive the protocol request by request.
+pub struct RawStream<Req, Resp> {
+    requests: mpsc::UnboundedSender<Req>,
+    responses: Streaming<Resp>,
+}
+
+impl<Req, Resp: std::fmt::Debug> RawStream<Req, Resp> {
+    pub fn send(&self, request: Req) {
+        self.requests.send(request).unwrap();
+    }
+
+    pub async fn next(&mut self) -> Resp {
+        match tokio::time::timeout(TIMEOUT, self.responses.message()).await {
+            Ok(Ok(Some(response))) => response,
+            Ok(result) => panic!("the stream ended: {result:?}"),
+            Err(_) => panic!("no response within {TIMEOUT:?}"),
+        }
+    }
+
+    /// Checks that no response comes for a while.
+    pub async fn assert_quiet(&mut self) {
+        if let Ok(result) = tokio::time::timeout(QUIET, self.responses.message()).await {
+            panic!("unexpected response: {result:?}");
+        }
+    }
+}
+
+pub type SotwStream = RawStream<DiscoveryRequest, DiscoveryResponse>;
+pub type DeltaStream = RawStream<DeltaDiscoveryRequest, DeltaDiscoveryResponse>;
+
+pub async fn open_sotw(server: &TestServer) -> SotwStream {
+    let (requests, receiver) = mpsc::unbounded_channel();
+    let mut client = AggregatedDiscoveryServiceClient::new(server.channel());
+    let responses = client.stream_aggregated_resources(UnboundedReceiverStream::new(receiver)).await.unwrap().into_inner();
+
+    RawStream { requests, responses }
+}
+
+pub async fn open_delta(server: &TestServer) -> DeltaStream {
+    let (requests, receiver) = mpsc::unbounded_channel();
+    let mut client = AggregatedDiscoveryServiceClient::new(server.channel());
+    let responses = client.delta_aggregated_resources(UnboundedReceiverStream::new(receiver)).await.unwrap().into_inner();
+
+    RawStream { requests, responses }
+}
+
+pub fn error_detail(message: &str) -> Option<rpc::Status> {
+    Some(rpc::Status {
+        code: tonic::Code::InvalidArgument as i32,
+        message: message.to_string(),
+        details: vec![],
+    })
+}
+
+/// Records the ACKs and NACKs of a server, as `ack <type> <version>` and
+/// `nack <type> <version> <message>` with the short type names.
+#[derive(Default)]
+pub struct Recorder(Mutex<Vec<String>>);
+
+impl Recorder {
+    /// Returns the events recorded since the last call.
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+fn get_short_name(type_url: &str) -> &str {
+    type_url.rsplit('.').next().unwrap()
+}
+
+impl Callbacks for Recorder {
+    fn on_ack(&self, _: &Node, type_url: &str, version: &str) {
+        self.0.lock().unwrap().push(format!("ack {} {version}", get_short_name(type_url)));
+    }
+
+    fn on_nack(&self, _: &Node, type_url: &str, version: &str, error: &rpc::Status) {
+        self.0.lock().unwrap().push(format!("nack {} {version} {}", get_short_name(type_url), error.message));
+    }
+}
@@ -0,0 +1,233 @@
+use envoypb::control_plane::{Inconsistency, ResourceRef, Resources, Snapshot};
+use envoypb::envoy::config::cluster::v3::cluster::{ClusterDiscoveryType, DiscoveryType, EdsClusterConfig};
+use envoypb::envoy::config::cluster::v3::Cluster;
+use envoypb::envoy::config::core::v3::config_source::ConfigSourceSpecifier;
+use envoypb::envoy::config::core::v3::{transport_socket, AggregatedConfigSource, ConfigSource, TransportSocket};
+use envoypb::envoy::config::endpoint::v3::ClusterLoadAssignment;
+use envoypb::envoy::config::listener::v3::{filter, Filter, FilterChain, Listener};
+use envoypb::envoy::config::route::v3::{RouteConfiguration, ScopedRouteConfiguration};
+use envoypb::envoy::extensions::filters::network::http_connection_manager::v3::{
+    http_connection_manager::RouteSpecifier, scoped_routes::ConfigSpecifier, HttpConnectionManager, Rds, ScopedRds,
+    ScopedRoutes,
+};
+use envoypb::envoy::extensions::transport_sockets::tls::v3::{CommonTlsContext, SdsSecretConfig, Secret, UpstreamTlsContext};
+use envoypb::google::protobuf::Any;
+use envoypb::resource::type_url;
+use prost::Name;
+
+fn ads() -> Option<ConfigSource> {
+    Some(ConfigSource {
+        config_source_specifier: Some(ConfigSourceSpecifier::Ads(AggregatedConfigSource {})),
+        ..Default::default()
+    })
+}
+
+fn resource(type_url: &str, name: &str) -> ResourceRef {
+    ResourceRef { type_url: type_url.to_string(), name: name.to_string() }
+}
+
+fn dangling(from: ResourceRef, to: ResourceRef) -> Inconsistency {
+    Inconsistency::Dangling { from, to }
+}
+
+fn listener(name: &str, manager: &HttpConnectionManager) -> Listener {
+    Listener {
+        name: name.to_string(),
+        filter_chains: vec![FilterChain {
+            filters: vec![Filter {
+                name: "envoy.filters.network.http_connection_manager".to_string(),
+                config_type: Some(filter::ConfigType::TypedConfig(Any::pack(manager))),
+            }],
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+fn rds(route_config_name: &str) -> HttpConnectionManager {
+    HttpConnectionManager {
+        route_specifier: Some(RouteSpecifier::Rds(Rds {
+            config_source: ads(),
+            route_config_name: route_config_name.to_string(),
+        })),
+        ..Default::default()
+    }
+}
+
+fn eds_cluster(name: &str, service_name: &str) -> Cluster {
+    Cluster {
+        name: name.to_string(),
+        cluster_discovery_type: Some(ClusterDiscoveryType::Type(DiscoveryType::Eds as i32)),
+        eds_cluster_config: Some(EdsClusterConfig {
+            eds_config: ads(),
+            service_name: service_name.to_string(),
+        }),
+        ..Default::default()
+    }
+}
+
+fn assignment(cluster_name: &str) -> ClusterLoadAssignment {
+    ClusterLoadAssignment {
+        cluster_name: cluster_name.to_string(),
+        ..Default::default()
+    }
+}
+
+fn route_configuration(name: &str) -> RouteConfiguration {
+    RouteConfiguration {
+        name: name.to_string(),
+        ..Default::default()
+    }
+}
+
+fn secret(name: &str) -> Secret {
+    Secret {
+        name: name.to_string(),
+        ..Default::default()
+    }
+}
+
+fn get_inconsistencies(snapshot: &Snapshot) -> Vec<Inconsistency> {
+    snapshot.check_consistency().unwrap_err().inconsistencies
+}
+
+#[test]
+fn accepts_consistent_snapshots() {
+    let snapshot = Snapshot::new()
+        .with("v1", [listener("http", &rds("routes"))])
+        .with("v1", [route_configuration("routes")])
+        .with("v1", [eds_cluster("backend", "")])
+        .with("v1", [assignment("backend")]);
+
+    snapshot.check_consistency().unwrap();
+}
+
+#[test]
+fn reports_dangling_route_configurations() {
+    let snapshot = Snapshot::new().with("v1", [listener("http", &rds("routes"))]);
+
+    assert_eq!(get_inconsistencies(&snapshot), [dangling(
+        resource(type_url::LISTENER, "http"),
+        resource(type_url::ROUTE_CONFIGURATION, "routes"),
+    )]);
+}
+
+#[test]
+fn reports_dangling_cluster_load_assignments() {
+    let snapshot = Snapshot::new().with("v1", [eds_cluster("backend", "")]);
+
+    assert_eq!(get_inconsistencies(&snapshot), [dangling(
+        resource(type_url::CLUSTER, "backend"),
+        resource(type_url::CLUSTER_LOAD_ASSIGNMENT, "backend"),
+    )]);
+}
+
+#[test]
+fn reports_dangling_secrets() {
+    let context = UpstreamTlsContext {
+        common_tls_context: Some(CommonTlsContext {
+            tls_certificate_sds_secret_configs: vec![SdsSecretConfig { name: "cert".to_string(), sds_config: ads() }],
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let cluster = Cluster {
+        name: "backend".to_string(),
+        transport_socket: Some(TransportSocket {
+            name: "envoy.transport_sockets.tls".to_string(),
+            config_type: Some(transport_socket::ConfigType::TypedConfig(Any::pack(&context))),
+        }),
+        ..Default::default()
+    };
+
+    let snapshot = Snapshot::new().with("v1", [cluster]);
+
+    assert_eq!(get_inconsistencies(&snapshot), [dangling(
+        resource(type_url::CLUSTER, "backend"),
+        resource(type_url::SECRET, "cert"),
+    )]);
+}
+
+#[test]
+fn reports_unused_leaf_resources() {
+    let snapshot = Snapshot::new()
+        .with("v1", [assignment("backend")])
+        .with("v1", [route_configuration("routes")])
+        .with("v1", [ScopedRouteConfiguration { name: "scope".to_string(), ..Default::default() }])
+        .with("v1", [secret("cert")]);
+
+    assert_eq!(get_inconsistencies(&snapshot), [
+        Inconsistency::Unused(resource(type_url::CLUSTER_LOAD_ASSIGNMENT, "backend")),
+        Inconsistency::Unused(resource(type_url::ROUTE_CONFIGURATION, "routes")),
+        Inconsistency::Unused(resource(type_url::SCOPED_ROUTE_CONFIGURATION, "scope")),
+        Inconsistency::Unused(resource(type_url::SECRET, "cert")),
+    ]);
+}
+
+#[test]
+fn treats_scoped_route_configurations_as_subscribed_by_wildcard() {
+    let manager = HttpConnectionManager {
+        route_specifier: Some(RouteSpecifier::ScopedRoutes(ScopedRoutes {
+            config_specifier: Some(ConfigSpecifier::ScopedRds(ScopedRds {
+                scoped_rds_config_source: ads(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+
+    let scope = ScopedRouteConfiguration {
+        name: "scope".to_string(),
+        route_configuration_name: "routes".to_string(),
+        ..Default::default()
+    };
+
+    let snapshot = Snapshot::new()
+        .with("v1", [listener("http", &manager)])
+        .with("v1", [scope]);
+
+    // The scope is not unused, but the route configuration it names is missing.
+    assert_eq!(get_inconsistencies(&snapshot), [dangling(
+        resource(type_url::SCOPED_ROUTE_CONFIGURATION, "scope"),
+        resource(type_url::ROUTE_CONFIGURATION, "routes"),
+    )]);
+}
+
+#[test]
+fn names_cluster_load_assignments_after_the_service_name() {
+    let snapshot = Snapshot::new()
+        .with("v1", [eds_cluster("backend", "service")])
+        .with("v1", [assignment("service"), assignment("backend")]);
+
+    assert_eq!(get_inconsistencies(&snapshot), [
+        Inconsistency::Unused(resource(type_url::CLUSTER_LOAD_ASSIGNMENT, "backend")),
+    ]);
+}
+
+#[test]
+fn reports_resources_that_cannot_be_decoded() {
+    let bad_listener = Any { type_url: type_url::LISTENER.to_string(), value: vec![0xff] };
+    let bad_manager = Any { type_url: HttpConnectionManager::type_url(), value: vec![0xff] };
+
+    let mut http = listener("http", &HttpConnectionManager::default());
+    http.filter_chains[0].filters[0].config_type = Some(filter::ConfigType::TypedConfig(bad_manager.clone()));
+
+    let mut snapshot = Snapshot::new();
+    snapshot.insert(type_url::LISTENER, Resources {
+        version: "v1".to_string(),
+        items: [("bad".to_string(), bad_listener.clone()), ("http".to_string(), Any::pack(&http))].into(),
+    });
+
+    assert_eq!(get_inconsistencies(&snapshot), [
+        Inconsistency::Decode {
+            resource: resource(type_url::LISTENER, "bad"),
+            message: bad_listener.unpack::<Listener>().unwrap_err().to_string(),
+        },
+        Inconsistency::Decode {
+            resource: resource(type_url::LISTENER, "http"),
+            message: bad_manager.unpack::<HttpConnectionManager>().unwrap_err().to_string(),
+        },
+    ]);
+}
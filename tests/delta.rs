@@ -0,0 +1,216 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use envoypb::control_plane::{Server, Snapshot, SnapshotCache};
+use envoypb::envoy::config::cluster::v3::Cluster;
+use envoypb::envoy::config::route::v3::VirtualHost;
+use envoypb::envoy::service::discovery::v3::{DeltaDiscoveryRequest, DeltaDiscoveryResponse};
+use envoypb::resource::type_url;
+use common::{cluster, error_detail, node, open_delta, DeltaStream, Recorder, TestServer};
+
+fn start() -> (SnapshotCache, Arc<Recorder>, TestServer) {
+    let cache = SnapshotCache::new();
+    let recorder = Arc::new(Recorder::default());
+    let server = TestServer::start(Server::new(cache.clone()).with_callbacks(recorder.clone()));
+
+    (cache, recorder, server)
+}
+
+fn subscribe(names: &[&str]) -> DeltaDiscoveryRequest {
+    DeltaDiscoveryRequest {
+        node: Some(node("envoy-1")),
+        type_url: type_url::CLUSTER.to_string(),
+        resource_names_subscribe: names.iter().map(|x| x.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+fn ack(response: &DeltaDiscoveryResponse) -> DeltaDiscoveryRequest {
+    DeltaDiscoveryRequest {
+        type_url: response.type_url.clone(),
+        response_nonce: response.nonce.clone(),
+        ..Default::default()
+    }
+}
+
+/// Returns the `stat_name` of every resource of a response, by name.
+fn get_updated(response: &DeltaDiscoveryResponse) -> Vec<(String, String)> {
+    response.resources.iter()
+        .map(|x| {
+            let cluster: Cluster = x.resource.as_ref().unwrap().unpack().unwrap();
+            assert_eq!(x.name, cluster.name);
+            (x.name.clone(), cluster.alt_stat_name)
+        })
+        .collect()
+}
+
+fn get_versions(response: &DeltaDiscoveryResponse) -> HashMap<String, String> {
+    response.resources.iter()
+        .map(|x| (x.name.clone(), x.version.clone()))
+        .collect()
+}
+
+fn pairs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+    items.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect()
+}
+
+/// Sends a request, and ACKs the response it gets.
+async fn exchange(stream: &mut DeltaStream, request: DeltaDiscoveryRequest) -> DeltaDiscoveryResponse {
+    stream.send(request);
+    let response = stream.next().await;
+    stream.send(ack(&response));
+    response
+}
+
+#[tokio::test]
+async fn sends_only_what_changed() {
+    let (cache, recorder, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", "1"), cluster("b", "1"), cluster("c", "1")]));
+
+    let mut stream = open_delta(&server).await;
+    let response = exchange(&mut stream, subscribe(&[])).await;
+    assert_eq!(response.system_version_info, "v1");
+    assert_eq!(get_updated(&response), pairs(&[("a", "1"), ("b", "1"), ("c", "1")]));
+    assert!(response.removed_resources.is_empty());
+
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v2", [cluster("a", "2"), cluster("b", "1")]));
+    let response = stream.next().await;
+    assert_eq!(response.system_version_info, "v2");
+    assert_eq!(get_updated(&response), pairs(&[("a", "2")]));
+    assert_eq!(response.removed_resources, ["c"]);
+
+    stream.send(ack(&response));
+    stream.assert_quiet().await;
+    assert_eq!(recorder.take(), ["ack Cluster v1", "ack Cluster v2"]);
+}
+
+#[tokio::test]
+async fn subscribes_and_unsubscribes_by_name() {
+    let (cache, _, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", "1"), cluster("b", "1")]));
+
+    let mut stream = open_delta(&server).await;
+
+    // Names that don't exist are reported as removed.
+    let response = exchange(&mut stream, subscribe(&["a", "missing"])).await;
+    assert_eq!(get_updated(&response), pairs(&[("a", "1")]));
+    assert_eq!(response.removed_resources, ["missing"]);
+
+    let response = exchange(&mut stream, subscribe(&["b"])).await;
+    assert_eq!(get_updated(&response), pairs(&[("b", "1")]));
+    assert!(response.removed_resources.is_empty());
+
+    stream.send(DeltaDiscoveryRequest {
+        resource_names_unsubscribe: vec!["a".to_string()],
+        ..subscribe(&[])
+    });
+    stream.assert_quiet().await;
+
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v2", [cluster("a", "2"), cluster("b", "2")]));
+    let response = stream.next().await;
+    assert_eq!(get_updated(&response), pairs(&[("b", "2")]));
+}
+
+#[tokio::test]
+async fn toggles_the_wildcard_subscription() {
+    let (cache, _, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", "1"), cluster("b", "1")]));
+
+    let mut stream = open_delta(&server).await;
+    exchange(&mut stream, subscribe(&["a"])).await;
+
+    let response = exchange(&mut stream, subscribe(&["*"])).await;
+    assert_eq!(get_updated(&response), pairs(&[("b", "1")]));
+
+    stream.send(DeltaDiscoveryRequest {
+        resource_names_unsubscribe: vec!["*".to_string()],
+        ..subscribe(&[])
+    });
+    stream.assert_quiet().await;
+
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v2", [cluster("a", "2"), cluster("b", "2")]));
+    let response = stream.next().await;
+    assert_eq!(get_updated(&response), pairs(&[("a", "2")]));
+}
+
+#[tokio::test]
+async fn resumes_from_the_initial_resource_versions() {
+    let (cache, _, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", "1"), cluster("b", "1")]));
+
+    let mut stream = open_delta(&server).await;
+    let versions = get_versions(&exchange(&mut stream, subscribe(&[])).await);
+    drop(stream);
+
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v2", [cluster("a", "1"), cluster("c", "1")]));
+
+    // The client has `a` at its current version, and `b` is gone.
+    let mut stream = open_delta(&server).await;
+    let response = exchange(&mut stream, DeltaDiscoveryRequest {
+        initial_resource_versions: versions,
+        ..subscribe(&[])
+    }).await;
+    assert_eq!(get_updated(&response), pairs(&[("c", "1")]));
+    assert_eq!(response.removed_resources, ["b"]);
+}
+
+#[tokio::test]
+async fn resends_nacked_resources_with_the_next_snapshot() {
+    let (cache, recorder, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", "1"), cluster("b", "1")]));
+
+    let mut stream = open_delta(&server).await;
+    exchange(&mut stream, subscribe(&[])).await;
+
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v2", [cluster("a", "2"), cluster("b", "1")]));
+    let response = stream.next().await;
+    assert_eq!(get_updated(&response), pairs(&[("a", "2")]));
+
+    stream.send(DeltaDiscoveryRequest {
+        error_detail: error_detail("bad cluster"),
+        ..ack(&response)
+    });
+    stream.assert_quiet().await;
+    assert_eq!(recorder.take(), ["ack Cluster v1", "nack Cluster v2 bad cluster"]);
+
+    // The client still has `a` at its first version.
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v3", [cluster("a", "2"), cluster("b", "3")]));
+    let response = stream.next().await;
+    assert_eq!(get_updated(&response), pairs(&[("a", "2"), ("b", "3")]));
+}
+
+#[tokio::test]
+async fn removes_every_resource_of_a_removed_type() {
+    let (cache, _, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", "1"), cluster("b", "1")]));
+
+    let mut stream = open_delta(&server).await;
+    exchange(&mut stream, subscribe(&[])).await;
+
+    cache.set_snapshot("envoy-1", Snapshot::new());
+    let response = stream.next().await;
+    assert!(response.resources.is_empty());
+    assert_eq!(response.removed_resources, ["a", "b"]);
+}
+
+#[tokio::test]
+async fn names_virtual_hosts_by_route_configuration_and_domain() {
+    let (cache, _, server) = start();
+    let host = VirtualHost {
+        name: "local_service".to_string(),
+        domains: vec!["example.com".to_string(), "www.example.com".to_string()],
+        ..Default::default()
+    };
+    cache.set_snapshot("envoy-1", Snapshot::new().with_virtual_hosts("v1", "local_route", [host.clone()]));
+
+    let mut stream = open_delta(&server).await;
+    let response = exchange(&mut stream, DeltaDiscoveryRequest {
+        type_url: type_url::VIRTUAL_HOST.to_string(),
+        ..subscribe(&["local_route/www.example.com"])
+    }).await;
+
+    assert_eq!(response.resources.len(), 1);
+    assert_eq!(response.resources[0].name, "local_route/www.example.com");
+    assert_eq!(response.resources[0].resource.as_ref().unwrap().unpack::<VirtualHost>().unwrap(), host);
+}
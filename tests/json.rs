@@ -0,0 +1,181 @@
+use envoypb::envoy::config::bootstrap::v3::Bootstrap;
+use envoypb::envoy::extensions::filters::network::http_connection_manager::v3::HttpConnectionManager;
+use envoypb::google::protobuf::{value, Any, Duration};
+use serde_json::json;
+
+/// The `envoy-demo.yaml` of the Envoy repository, with metadata added.
+fn get_demo() -> serde_json::Value {
+    json!({
+        "node": {
+            "id": "envoy-1",
+            "metadata": {"region": "eu-west-1", "canary": false, "weight": 2.5, "tags": ["a", null]}
+        },
+        "static_resources": {
+            "listeners": [{
+                "name": "listener_0",
+                "address": {"socket_address": {"address": "0.0.0.0", "port_value": 10000}},
+                "filter_chains": [{
+                    "filters": [{
+                        "name": "envoy.filters.network.http_connection_manager",
+                        "typed_config": {
+                            "@type": "type.googleapis.com/envoy.extensions.filters.network.http_connection_manager.v3.HttpConnectionManager",
+                            "stat_prefix": "ingress_http",
+                            "stream_idle_timeout": "300.500s",
+                            "access_log": [{
+                                "name": "envoy.access_loggers.stdout",
+                                "typed_config": {
+                                    "@type": "type.googleapis.com/envoy.extensions.access_loggers.stream.v3.StdoutAccessLog"
+                                }
+                            }],
+                            "http_filters": [{
+                                "name": "envoy.filters.http.router",
+                                "typed_config": {
+                                    "@type": "type.googleapis.com/envoy.extensions.filters.http.router.v3.Router"
+                                }
+                            }],
+                            "route_config": {
+                                "name": "local_route",
+                                "virtual_hosts": [{
+                                    "name": "local_service",
+                                    "domains": ["*"],
+                                    "routes": [{
+                                        "match": {"prefix": "/"},
+                                        "route": {"host_rewrite_literal": "www.envoyproxy.io", "cluster": "service_envoyproxy_io"}
+                                    }]
+                                }]
+                            }
+                        }
+                    }]
+                }]
+            }],
+            "clusters": [{
+                "name": "service_envoyproxy_io",
+                "type": "LOGICAL_DNS",
+                "connect_timeout": "0.25s",
+                "dns_lookup_family": "V4_ONLY",
+                "metadata": {"filter_metadata": {"envoy.lb": {"version": "v1", "shard": 3}}},
+                "load_assignment": {
+                    "cluster_name": "service_envoyproxy_io",
+                    "endpoints": [{
+                        "lb_endpoints": [{
+                            "endpoint": {
+                                "address": {"socket_address": {"address": "www.envoyproxy.io", "port_value": 443}}
+                            }
+                        }]
+                    }]
+                },
+                "transport_socket": {
+                    "name": "envoy.transport_sockets.tls",
+                    "typed_config": {
+                        "@type": "type.googleapis.com/envoy.extensions.transport_sockets.tls.v3.UpstreamTlsContext",
+                        "sni": "www.envoyproxy.io"
+                    }
+                }
+            }]
+        }
+    })
+}
+
+#[test]
+fn round_trips_an_envoy_config() {
+    let bootstrap: Bootstrap = serde_json::from_value(get_demo()).unwrap();
+
+    let resources = bootstrap.static_resources.as_ref().unwrap();
+    let cluster = &resources.clusters[0];
+    assert_eq!(cluster.connect_timeout, Some(Duration { seconds: 0, nanos: 250_000_000 }));
+
+    let metadata = &cluster.metadata.as_ref().unwrap().filter_metadata["envoy.lb"];
+    assert_eq!(metadata.fields["shard"].kind, Some(value::Kind::NumberValue(3.0)));
+
+    let filter = &resources.listeners[0].filter_chains[0].filters[0];
+    let Some(envoypb::envoy::config::listener::v3::filter::ConfigType::TypedConfig(any)) = &filter.config_type else {
+        panic!("no typed_config");
+    };
+    let manager: HttpConnectionManager = any.unpack().unwrap();
+    assert_eq!(manager.stat_prefix, "ingress_http");
+    assert_eq!(manager.stream_idle_timeout, Some(Duration { seconds: 300, nanos: 500_000_000 }));
+    assert_eq!(manager.http_filters[0].name, "envoy.filters.http.router");
+
+    // Fields are written in lowerCamelCase, which is read back as well.
+    let json = serde_json::to_value(&bootstrap).unwrap();
+    assert_eq!(json["staticResources"]["clusters"][0]["connectTimeout"], "0.250s");
+    assert_eq!(json["staticResources"]["clusters"][0]["metadata"]["filterMetadata"]["envoy.lb"], json!({"version": "v1", "shard": 3.0}));
+    assert_eq!(json["node"]["metadata"], json!({"region": "eu-west-1", "canary": false, "weight": 2.5, "tags": ["a", null]}));
+
+    let config = &json["staticResources"]["listeners"][0]["filterChains"][0]["filters"][0]["typedConfig"];
+    assert_eq!(config["@type"], "type.googleapis.com/envoy.extensions.filters.network.http_connection_manager.v3.HttpConnectionManager");
+    assert_eq!(config["streamIdleTimeout"], "300.500s");
+    assert_eq!(config["httpFilters"][0]["typedConfig"], json!({"@type": "type.googleapis.com/envoy.extensions.filters.http.router.v3.Router"}));
+
+    let again: Bootstrap = serde_json::from_value(json).unwrap();
+    assert_eq!(again, bootstrap);
+}
+
+#[test]
+fn round_trips_an_envoy_config_through_yaml() {
+    // `configs/envoy-demo.yaml` of the Envoy repository, as is.
+    let bootstrap: Bootstrap = serde_yaml::from_str(include_str!("data/envoy-demo.yaml")).unwrap();
+
+    let resources = bootstrap.static_resources.as_ref().unwrap();
+    assert_eq!(resources.clusters[0].name, "service_envoyproxy_io");
+
+    let filter = &resources.listeners[0].filter_chains[0].filters[0];
+    let Some(envoypb::envoy::config::listener::v3::filter::ConfigType::TypedConfig(any)) = &filter.config_type else {
+        panic!("no typed_config");
+    };
+    let manager: HttpConnectionManager = any.unpack().unwrap();
+    assert_eq!(manager.stat_prefix, "ingress_http");
+
+    let yaml = serde_yaml::to_string(&bootstrap).unwrap();
+    assert!(yaml.contains("'@type': type.googleapis.com/envoy.extensions.transport_sockets.tls.v3.UpstreamTlsContext"), "{yaml}");
+
+    let again: Bootstrap = serde_yaml::from_str(&yaml).unwrap();
+    assert_eq!(again, bootstrap);
+}
+
+// The well-known types of pbjson-types do not implement `prost::Name`.
+#[cfg(not(feature = "extern-wkt"))]
+#[test]
+fn embeds_well_known_types_in_any_under_value() {
+    use envoypb::google::protobuf::Value;
+
+    let items = [
+        (Any::pack(&Duration { seconds: 1, nanos: 0 }), json!("1s")),
+        (Any::pack(&Value { kind: Some(value::Kind::StringValue("x".to_string())) }), json!("x")),
+    ];
+
+    assert_eq!(items[0].0.type_url, "type.googleapis.com/google.protobuf.Duration");
+
+    for (any, value) in items {
+        let json = serde_json::to_value(&any).unwrap();
+        assert_eq!(json, json!({"@type": any.type_url, "value": value}));
+        assert_eq!(serde_json::from_value::<Any>(json).unwrap(), any);
+    }
+}
+
+#[test]
+fn rejects_unknown_types() {
+    let result = serde_json::from_value::<Any>(json!({"@type": "type.googleapis.com/example.Unknown"}));
+    assert!(result.unwrap_err().to_string().contains("unknown type URL"));
+}
+
+// With `extern-wkt`, the wrappers and their JSON mapping come from pbjson-types.
+#[cfg(not(feature = "extern-wkt"))]
+#[test]
+fn reads_wrapped_numbers_from_either_spelling() {
+    use envoypb::envoy::config::cluster::v3::Cluster;
+
+    let get_limit = |value| {
+        let json = json!({"name": "a", "per_connection_buffer_limit_bytes": value});
+        let cluster: Cluster = serde_json::from_value(json)?;
+        serde_json::to_value(cluster).map(|x| x["perConnectionBufferLimitBytes"].clone())
+    };
+
+    for value in [json!(32768), json!("32768"), json!(32768.0), json!("3.2768e4")] {
+        assert_eq!(get_limit(value).unwrap(), 32768);
+    }
+
+    for value in [json!(-1), json!("1.5"), json!(4294967296u64), json!("")] {
+        assert!(get_limit(value).is_err());
+    }
+}
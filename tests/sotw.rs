@@ -0,0 +1,179 @@
+mod common;
+
+use std::sync::Arc;
+use envoypb::control_plane::{Server, Snapshot, SnapshotCache};
+use envoypb::envoy::config::cluster::v3::Cluster;
+use envoypb::envoy::service::discovery::v3::{DiscoveryRequest, DiscoveryResponse};
+use envoypb::resource::type_url;
+use common::{cluster, error_detail, node, open_sotw, Recorder, TestServer};
+
+fn start() -> (SnapshotCache, Arc<Recorder>, TestServer) {
+    let cache = SnapshotCache::new();
+    let recorder = Arc::new(Recorder::default());
+    let server = TestServer::start(Server::new(cache.clone()).with_callbacks(recorder.clone()));
+
+    (cache, recorder, server)
+}
+
+fn subscribe(names: &[&str]) -> DiscoveryRequest {
+    DiscoveryRequest {
+        node: Some(node("envoy-1")),
+        type_url: type_url::CLUSTER.to_string(),
+        resource_names: names.iter().map(|x| x.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+fn ack(response: &DiscoveryResponse) -> DiscoveryRequest {
+    DiscoveryRequest {
+        version_info: response.version_info.clone(),
+        type_url: response.type_url.clone(),
+        response_nonce: response.nonce.clone(),
+        ..Default::default()
+    }
+}
+
+fn get_names(response: &DiscoveryResponse) -> Vec<String> {
+    response.resources.iter()
+        .map(|x| x.unpack::<Cluster>().unwrap().name)
+        .collect()
+}
+
+#[tokio::test]
+async fn sends_the_snapshot_and_records_acks() {
+    let (cache, recorder, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", ""), cluster("b", "")]));
+
+    let mut stream = open_sotw(&server).await;
+    stream.send(subscribe(&[]));
+
+    let response = stream.next().await;
+    assert_eq!(response.version_info, "v1");
+    assert_eq!(response.type_url, type_url::CLUSTER);
+    assert_eq!(get_names(&response), ["a", "b"]);
+    assert!(!response.nonce.is_empty());
+
+    stream.send(ack(&response));
+    stream.assert_quiet().await;
+    assert_eq!(recorder.take(), ["ack Cluster v1"]);
+}
+
+#[tokio::test]
+async fn sends_only_the_subscribed_resources() {
+    let (cache, _, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", ""), cluster("b", "")]));
+
+    let mut stream = open_sotw(&server).await;
+    stream.send(subscribe(&["b", "missing"]));
+
+    let response = stream.next().await;
+    assert_eq!(get_names(&response), ["b"]);
+}
+
+#[tokio::test]
+async fn waits_for_the_snapshot_of_the_node() {
+    let (cache, _, server) = start();
+
+    let mut stream = open_sotw(&server).await;
+    stream.send(subscribe(&[]));
+    stream.assert_quiet().await;
+
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", "")]));
+    assert_eq!(stream.next().await.version_info, "v1");
+}
+
+#[tokio::test]
+async fn records_nacks_and_does_not_resend() {
+    let (cache, recorder, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", "")]));
+
+    let mut stream = open_sotw(&server).await;
+    stream.send(subscribe(&[]));
+    let response = stream.next().await;
+    stream.send(ack(&response));
+
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v2", [cluster("a", "2")]));
+    let response = stream.next().await;
+    assert_eq!(response.version_info, "v2");
+
+    // A NACK carries the version the client kept.
+    stream.send(DiscoveryRequest {
+        version_info: "v1".to_string(),
+        error_detail: error_detail("bad cluster"),
+        ..ack(&response)
+    });
+    stream.assert_quiet().await;
+    assert_eq!(recorder.take(), ["ack Cluster v1", "nack Cluster v2 bad cluster"]);
+
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v3", [cluster("a", "3")]));
+    assert_eq!(stream.next().await.version_info, "v3");
+}
+
+#[tokio::test]
+async fn ignores_stale_nonces() {
+    let (cache, recorder, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", ""), cluster("b", "")]));
+
+    let mut stream = open_sotw(&server).await;
+    stream.send(subscribe(&[]));
+    let response = stream.next().await;
+
+    // Neither an ACK nor a new subscription.
+    stream.send(DiscoveryRequest {
+        response_nonce: "stale".to_string(),
+        resource_names: vec!["a".to_string()],
+        ..ack(&response)
+    });
+    stream.assert_quiet().await;
+    assert_eq!(recorder.take(), Vec::<String>::new());
+
+    // The response is still awaiting its ACK.
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v2", [cluster("a", "2")]));
+    stream.assert_quiet().await;
+
+    stream.send(ack(&response));
+    let response = stream.next().await;
+    assert_eq!(response.version_info, "v2");
+    assert_eq!(recorder.take(), ["ack Cluster v1"]);
+}
+
+#[tokio::test]
+async fn pushes_snapshots_once_the_last_response_is_acked() {
+    let (cache, _, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", "")]));
+
+    let mut stream = open_sotw(&server).await;
+    stream.send(subscribe(&[]));
+    let response = stream.next().await;
+
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v2", [cluster("a", "2")]));
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v3", [cluster("a", "3")]));
+    stream.assert_quiet().await;
+
+    // Only the latest snapshot is sent.
+    stream.send(ack(&response));
+    let response = stream.next().await;
+    assert_eq!(response.version_info, "v3");
+
+    stream.send(ack(&response));
+    stream.assert_quiet().await;
+}
+
+#[tokio::test]
+async fn resends_on_new_subscriptions() {
+    let (cache, _, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", ""), cluster("b", "")]));
+
+    let mut stream = open_sotw(&server).await;
+    stream.send(subscribe(&["a"]));
+    let response = stream.next().await;
+    assert_eq!(get_names(&response), ["a"]);
+
+    stream.send(DiscoveryRequest {
+        resource_names: vec!["a".to_string(), "b".to_string()],
+        ..ack(&response)
+    });
+    let response = stream.next().await;
+    assert_eq!(response.version_info, "v1");
+    assert_eq!(get_names(&response), ["a", "b"]);
+}
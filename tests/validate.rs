@@ -0,0 +1,59 @@
+use envoypb::envoy::config::bootstrap::v3::{bootstrap::StaticResources, Bootstrap};
+use envoypb::envoy::config::cluster::v3::Cluster;
+use envoypb::envoy::config::core::v3::{address, socket_address, Address, SocketAddress};
+use envoypb::google::protobuf::Duration;
+use envoypb::{Validate, Violation};
+
+fn socket_address(address: &str, port: u32) -> Address {
+    Address {
+        address: Some(address::Address::SocketAddress(SocketAddress {
+            address: address.to_string(),
+            port_specifier: Some(socket_address::PortSpecifier::PortValue(port)),
+            ..Default::default()
+        })),
+    }
+}
+
+fn cluster(name: &str, connect_timeout: Duration) -> Cluster {
+    Cluster {
+        name: name.to_string(),
+        connect_timeout: Some(connect_timeout),
+        ..Default::default()
+    }
+}
+
+fn get_violations(message: &impl Validate) -> Vec<Violation> {
+    message.validate().unwrap_err().violations
+}
+
+#[test]
+fn accepts_valid_messages() {
+    socket_address("127.0.0.1", 8080).validate().unwrap();
+    socket_address("::", 65535).validate().unwrap();
+    cluster("backend", Duration { seconds: 1, nanos: 0 }).validate().unwrap();
+}
+
+#[test]
+fn reports_violations_with_their_path() {
+    let violations = get_violations(&socket_address("", 65536));
+    let fields: Vec<_> = violations.iter().map(|x| x.field.as_str()).collect();
+    assert_eq!(fields, ["socket_address.address", "socket_address.port_value"]);
+}
+
+#[test]
+fn checks_nested_messages() {
+    let bootstrap = Bootstrap {
+        static_resources: Some(StaticResources {
+            clusters: vec![
+                cluster("backend", Duration { seconds: 1, nanos: 0 }),
+                cluster("", Duration { seconds: 0, nanos: 0 }),
+            ],
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let violations = get_violations(&bootstrap);
+    let fields: Vec<_> = violations.iter().map(|x| x.field.as_str()).collect();
+    assert_eq!(fields, ["static_resources.clusters[1].name", "static_resources.clusters[1].connect_timeout"]);
+}
@@ -0,0 +1,31 @@
+use envoypb::google::protobuf::Duration;
+use envoypb::v1_30::envoy::config::cluster::v3::Cluster as ClusterV1_30;
+use envoypb::v1_32::envoy::config::cluster::v3::Cluster as ClusterV1_32;
+use envoypb::v1_32::envoy::config::listener::v3::Listener as ListenerV1_32;
+use envoypb::{convert, AnyError};
+
+fn cluster() -> ClusterV1_32 {
+    ClusterV1_32 {
+        name: "backend".to_string(),
+        alt_stat_name: "backend_stats".to_string(),
+        connect_timeout: Some(Duration { seconds: 1, nanos: 500_000_000 }),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn converts_messages_between_versions() {
+    let older: ClusterV1_30 = convert(&cluster()).unwrap();
+    assert_eq!(older.name, "backend");
+    assert_eq!(older.alt_stat_name, "backend_stats");
+    assert_eq!(older.connect_timeout, Some(Duration { seconds: 1, nanos: 500_000_000 }));
+
+    let newer: ClusterV1_32 = convert(&older).unwrap();
+    assert_eq!(newer, cluster());
+}
+
+#[test]
+fn rejects_messages_of_another_name() {
+    let result = convert::<_, ListenerV1_32>(&cluster());
+    assert!(matches!(result, Err(AnyError::TypeMismatch { .. })));
+}
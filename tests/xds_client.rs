@@ -0,0 +1,216 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use envoypb::control_plane::{Resources, Server, Snapshot, SnapshotCache};
+use envoypb::envoy::config::cluster::v3::Cluster;
+use envoypb::envoy::config::endpoint::v3::ClusterLoadAssignment;
+use envoypb::envoy::config::route::v3::VirtualHost;
+use envoypb::google::protobuf::Any;
+use envoypb::resource::type_url;
+use envoypb::xds_client::{ClientOptions, Protocol, Update, Watch, XdsClient};
+use common::{cluster, node, Recorder, TestServer, QUIET, TIMEOUT};
+
+fn start() -> (SnapshotCache, Arc<Recorder>, TestServer) {
+    let cache = SnapshotCache::new();
+    let recorder = Arc::new(Recorder::default());
+    let server = TestServer::start(get_server(&cache, &recorder));
+
+    (cache, recorder, server)
+}
+
+fn get_server(cache: &SnapshotCache, recorder: &Arc<Recorder>) -> Server {
+    Server::new(cache.clone()).with_callbacks(recorder.clone())
+}
+
+fn connect(server: &TestServer, protocol: Protocol) -> XdsClient {
+    let options = ClientOptions {
+        protocol,
+        initial_backoff: Duration::from_millis(50),
+        max_backoff: Duration::from_millis(200),
+    };
+
+    XdsClient::new(server.channel(), node("envoy-1"), options)
+}
+
+fn assignment(name: &str) -> ClusterLoadAssignment {
+    ClusterLoadAssignment {
+        cluster_name: name.to_string(),
+        ..Default::default()
+    }
+}
+
+async fn next<M: envoypb::resource::XdsResource + Default>(watch: &mut Watch<M>) -> Update<M> {
+    tokio::time::timeout(TIMEOUT, watch.changed()).await.expect("no update")
+}
+
+fn get_stat_names(update: &Update<Cluster>) -> Vec<(&str, &str)> {
+    update.resources.iter()
+        .map(|(name, x)| (name.as_str(), x.alt_stat_name.as_str()))
+        .collect()
+}
+
+async fn delivers_updates(protocol: Protocol) {
+    let (cache, _, server) = start();
+    let endpoints = [assignment("a"), assignment("b")];
+    cache.set_snapshot("envoy-1", Snapshot::new()
+        .with("v1", [cluster("a", "1"), cluster("b", "1")])
+        .with("v1", endpoints.clone()));
+
+    let client = connect(&server, protocol);
+    let mut clusters = client.watch_all::<Cluster>();
+    let mut assignments = client.watch::<ClusterLoadAssignment>(["a"]);
+
+    let update = next(&mut clusters).await;
+    assert_eq!(update.version, "v1");
+    assert_eq!(get_stat_names(&update), [("a", "1"), ("b", "1")]);
+    assert_eq!(next(&mut assignments).await.resources.keys().collect::<Vec<_>>(), ["a"]);
+
+    // Watches added later get what was received, or wait for it.
+    let mut more = client.watch::<ClusterLoadAssignment>(["b"]);
+    assert_eq!(next(&mut more).await.resources.keys().collect::<Vec<_>>(), ["b"]);
+    let mut again = client.watch::<Cluster>(["b"]);
+    assert_eq!(get_stat_names(&next(&mut again).await), [("b", "1")]);
+
+    cache.set_snapshot("envoy-1", Snapshot::new()
+        .with("v2", [cluster("a", "2"), cluster("b", "1")])
+        .with("v1", endpoints.clone()));
+
+    let update = next(&mut clusters).await;
+    assert_eq!(update.version, "v2");
+    assert_eq!(get_stat_names(&update), [("a", "2"), ("b", "1")]);
+
+    // Watches of other names are not woken.
+    assert!(tokio::time::timeout(QUIET, again.changed()).await.is_err());
+    assert!(tokio::time::timeout(QUIET, assignments.changed()).await.is_err());
+    assert_eq!(clusters.current(), Some(update));
+}
+
+#[tokio::test]
+async fn delivers_updates_sotw() {
+    delivers_updates(Protocol::StateOfTheWorld).await;
+}
+
+#[tokio::test]
+async fn delivers_updates_delta() {
+    delivers_updates(Protocol::Incremental).await;
+}
+
+async fn delivers_removals(protocol: Protocol) {
+    let (cache, _, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", "1"), cluster("b", "1")]));
+
+    let client = connect(&server, protocol);
+    let mut clusters = client.watch_all::<Cluster>();
+    let mut named = client.watch::<Cluster>(["b"]);
+    next(&mut clusters).await;
+    next(&mut named).await;
+
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v2", [cluster("a", "1")]));
+    assert_eq!(get_stat_names(&next(&mut clusters).await), [("a", "1")]);
+
+    let update = next(&mut named).await;
+    assert_eq!(update.version, "v2");
+    assert!(update.resources.is_empty());
+}
+
+#[tokio::test]
+async fn delivers_removals_sotw() {
+    delivers_removals(Protocol::StateOfTheWorld).await;
+}
+
+#[tokio::test]
+async fn delivers_removals_delta() {
+    delivers_removals(Protocol::Incremental).await;
+}
+
+async fn nacks_undecodable_resources(protocol: Protocol) {
+    let (cache, recorder, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", "1")]));
+
+    let client = connect(&server, protocol);
+    let mut clusters = client.watch_all::<Cluster>();
+    let update = next(&mut clusters).await;
+
+    let mut snapshot = Snapshot::new();
+    let mut items = BTreeMap::new();
+    items.insert("a".to_string(), Any::pack(&cluster("a", "2")));
+    items.insert("b".to_string(), Any { type_url: type_url::CLUSTER.to_string(), value: vec![0xff] });
+    snapshot.insert(type_url::CLUSTER, Resources { version: "v2".to_string(), items });
+    cache.set_snapshot("envoy-1", snapshot);
+
+    // The watch keeps the resources it had.
+    assert!(tokio::time::timeout(QUIET, clusters.changed()).await.is_err());
+    assert_eq!(clusters.current(), Some(update));
+
+    let events = recorder.take();
+    assert_eq!(events[0], "ack Cluster v1");
+    assert!(events[1].starts_with("nack Cluster v2 resource "), "{events:?}");
+
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v3", [cluster("a", "3")]));
+    assert_eq!(get_stat_names(&next(&mut clusters).await), [("a", "3")]);
+}
+
+#[tokio::test]
+async fn nacks_undecodable_resources_sotw() {
+    nacks_undecodable_resources(Protocol::StateOfTheWorld).await;
+}
+
+#[tokio::test]
+async fn nacks_undecodable_resources_delta() {
+    nacks_undecodable_resources(Protocol::Incremental).await;
+}
+
+async fn reconnects(protocol: Protocol) {
+    let (cache, recorder, server) = start();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v1", [cluster("a", "1"), cluster("b", "1")]));
+
+    let client = connect(&server, protocol);
+    let mut clusters = client.watch_all::<Cluster>();
+    next(&mut clusters).await;
+
+    // `b` is removed while the client is away. On incremental streams, only
+    // the versions the client resumes from tell the server it had `b`.
+    let addr = server.stop();
+    cache.set_snapshot("envoy-1", Snapshot::new().with("v2", [cluster("a", "1")]));
+    tokio::time::sleep(QUIET).await;
+
+    let _server = TestServer::start_at(get_server(&cache, &recorder), addr);
+    let update = next(&mut clusters).await;
+    assert_eq!(update.version, "v2");
+    assert_eq!(get_stat_names(&update), [("a", "1")]);
+
+    // Watches made while reconnected subscribe on the new stream.
+    let mut assignments = client.watch::<ClusterLoadAssignment>(["a"]);
+    cache.set_snapshot("envoy-1", Snapshot::new()
+        .with("v2", [cluster("a", "1")])
+        .with("v1", [assignment("a")]));
+    assert_eq!(next(&mut assignments).await.resources.keys().collect::<Vec<_>>(), ["a"]);
+}
+
+#[tokio::test]
+async fn reconnects_sotw() {
+    reconnects(Protocol::StateOfTheWorld).await;
+}
+
+#[tokio::test]
+async fn reconnects_delta() {
+    reconnects(Protocol::Incremental).await;
+}
+
+#[tokio::test]
+async fn keys_virtual_hosts_by_their_vhds_name() {
+    let (cache, _, server) = start();
+    let host = VirtualHost {
+        name: "local_service".to_string(),
+        domains: vec!["example.com".to_string()],
+        ..Default::default()
+    };
+    cache.set_snapshot("envoy-1", Snapshot::new().with_virtual_hosts("v1", "local_route", [host]));
+
+    let client = connect(&server, Protocol::Incremental);
+    let mut hosts = client.watch::<VirtualHost>(["local_route/example.com"]);
+    let update = next(&mut hosts).await;
+    assert_eq!(update.resources.keys().collect::<Vec<_>>(), ["local_route/example.com"]);
+}
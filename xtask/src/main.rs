@@ -0,0 +1,105 @@
+//! Maintainer tasks, run with `cargo xtask <task>`:
+//!
+//! - `generate [VERSION...]` writes the pre-generated sources of the given
+//!   Envoy API versions, or of every supported one, to `generated/`. They are
+//!   generated as the build script does with `regenerate` and no other
+//!   feature, so that builds without `regenerate` get the same code.
+//! - `pin [VERSION...]` downloads the GitHub tarball of every dependency at
+//!   the refs pinned for the given versions, or every supported one, and
+//!   prints their SHA-256 digests to pin in `codegen/src/regenerate.rs`. It
+//!   fails when a digest differs from the one already pinned.
+
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+fn get_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().to_path_buf()
+}
+
+fn get_module_name(api_version: &str) -> String {
+    format!("v{}", api_version.replace(".", "_"))
+}
+
+/// Returns the versions given, or every supported one.
+fn get_versions(versions: &[String]) -> Vec<&str> {
+    let supported = envoypb_codegen::get_api_versions();
+
+    let versions: Vec<&str> = match versions.is_empty() {
+        true => supported.clone(),
+        false => versions.iter().map(|x| x.as_str()).collect(),
+    };
+
+    if let Some(version) = versions.iter().find(|x| !supported.contains(x)) {
+        eprintln!("unsupported Envoy API version {version}, supported versions are: {}", supported.join(", "));
+        process::exit(1);
+    }
+
+    versions
+}
+
+fn generate(versions: &[String]) {
+    let root = get_root();
+    let versions = get_versions(versions);
+
+    // The generator reads what cargo would set for envoypb's build script.
+    // Features are left unset, as the pre-generated sources have none of the
+    // ones changing the generated code.
+    env::set_var("CARGO_MANIFEST_DIR", &root);
+    env::set_var("OUT_DIR", root.join("target").join("xtask"));
+
+    for (key, _) in env::vars().filter(|(key, _)| key.starts_with("CARGO_FEATURE_")) {
+        env::remove_var(key);
+    }
+
+    for version in versions {
+        let out_dir = root.join("generated").join(get_module_name(version));
+
+        // Files of packages gone from the version must not be left behind.
+        if out_dir.exists() {
+            fs::remove_dir_all(&out_dir).unwrap();
+        }
+
+        eprintln!("generating Envoy API {version} in {}", out_dir.display());
+        envoypb_codegen::generate(version, &out_dir);
+    }
+}
+
+fn pin(versions: &[String]) {
+    let dir = get_root().join("target").join("xtask").join("pin");
+    let mut mismatches = 0;
+
+    for version in get_versions(versions) {
+        let digests = envoypb_codegen::get_github_digests(version, &dir.join(version)).unwrap_or_else(|error| {
+            eprintln!("{error}");
+            process::exit(1);
+        });
+
+        for x in digests {
+            match x.pinned {
+                Some(pinned) if pinned != x.digest => {
+                    println!("{version} {} {} {} (pinned {pinned})", x.key, x.ref_, x.digest);
+                    mismatches += 1;
+                },
+                _ => println!("{version} {} {} {}", x.key, x.ref_, x.digest),
+            }
+        }
+    }
+
+    if mismatches > 0 {
+        eprintln!("{mismatches} digest(s) differ from the pinned ones");
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.split_first() {
+        Some((task, versions)) if task == "generate" => generate(versions),
+        Some((task, versions)) if task == "pin" => pin(versions),
+        _ => {
+            eprintln!("usage: cargo xtask (generate | pin) [VERSION...]");
+            process::exit(2);
+        },
+    }
+}